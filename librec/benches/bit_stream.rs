@@ -0,0 +1,150 @@
+// Compares the word-at-a-time BitReader/BitWriter against the byte-at-a-time
+// path every other stream gets from the BitRead/BitWrite defaults.
+//
+// Put some recs in a folder and point REC_BENCH_DIR at it to benchmark real
+// files, otherwise a generated recording is used.
+
+#[macro_use]
+extern crate criterion;
+
+use std::env;
+use std::fs;
+use criterion::{black_box, Criterion};
+use librec::bit_stream::{BitRead, BitReader, BitWrite, BitWriter};
+use librec::encoding::Encoding;
+use librec::error::Result;
+use librec::recording::{Angle, Axis, Frame, Move, Recording, Triggers};
+
+// Only has the single byte primitives, so everything else falls back to the
+// old way of splitting reads and writes into bytes
+struct ByteAtATime<T>(T);
+
+impl<'a> BitRead for ByteAtATime<BitReader<'a>> {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        self.0.read_bits_u8(bits)
+    }
+
+    fn eof(&mut self) -> bool {
+        self.0.eof()
+    }
+}
+
+impl BitWrite for ByteAtATime<BitWriter> {
+    fn write_bits_u8(&mut self, value: u8, bits: u8) -> Result<()> {
+        self.0.write_bits_u8(value, bits)
+    }
+}
+
+fn load_recs() -> Vec<Vec<u8>> {
+    let dir = match env::var("REC_BENCH_DIR") {
+        Ok(dir) => dir,
+        Err(_) => return vec![generated_rec()],
+    };
+    let recs = fs::read_dir(&dir)
+        .expect("Cannot read REC_BENCH_DIR")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rec"))
+        .filter_map(|path| fs::read(path).ok())
+        .collect::<Vec<_>>();
+    assert!(!recs.is_empty(), "No recs in {}", dir);
+    recs
+}
+
+// About five minutes of 60fps input
+fn generated_rec() -> Vec<u8> {
+    let frames = (0..18000u32)
+        .map(|i| {
+            let angle = |n: u32| Some(Angle((n % 65536) as u16));
+            let mv = Move {
+                yaw: angle(i * 37),
+                pitch: angle(i * 11),
+                roll: None,
+                mx: Axis((i % 33) as u8),
+                my: Axis(((i / 3) % 33) as u8),
+                mz: Axis(16),
+                freelook: true,
+                triggers: Triggers::from([i % 7 == 0, false, i % 5 == 0, false, false, false]),
+            };
+            Frame {
+                moves: [Some(mv), if i % 2 == 0 { Some(mv) } else { None }],
+                delta: 16 + (i % 2) as u16,
+            }
+        })
+        .collect();
+    Recording {
+        mission: "marble/data/missions/intermediate/tubetreasure.mis".into(),
+        frames,
+        mission_encoding: Encoding::Utf8,
+        framing: None,
+        metadata: None,
+    }
+    .into_bytes()
+    .unwrap()
+}
+
+fn parse(c: &mut Criterion) {
+    let recs = load_recs();
+    c.bench_function("parse words", |b| {
+        b.iter(|| {
+            for rec in &recs {
+                black_box(Recording::from_stream(&mut BitReader::new(rec)).unwrap());
+            }
+        })
+    });
+    c.bench_function("parse bytes", |b| {
+        b.iter(|| {
+            for rec in &recs {
+                black_box(Recording::from_stream(&mut ByteAtATime(BitReader::new(rec))).unwrap());
+            }
+        })
+    });
+}
+
+fn write(c: &mut Criterion) {
+    let recordings = load_recs()
+        .iter()
+        .map(|rec| Recording::from_bytes(rec).unwrap())
+        .collect::<Vec<_>>();
+    c.bench_function("write words", |b| {
+        b.iter(|| {
+            for recording in &recordings {
+                let mut bs = BitWriter::new();
+                recording.clone().into_stream(&mut bs).unwrap();
+                black_box(bs.bytes());
+            }
+        })
+    });
+    c.bench_function("write bytes", |b| {
+        b.iter(|| {
+            for recording in &recordings {
+                let mut bs = ByteAtATime(BitWriter::new());
+                recording.clone().into_stream(&mut bs).unwrap();
+                black_box(bs.0.bytes());
+            }
+        })
+    });
+}
+
+fn read_u64(c: &mut Criterion) {
+    let data = (0..64 * 1024).map(|i| (i * 31) as u8).collect::<Vec<_>>();
+    c.bench_function("read_bits_u64 words", |b| {
+        b.iter(|| {
+            let mut bs = BitReader::new(&data);
+            while let Ok(value) = bs.read_bits_u64(61) {
+                black_box(value);
+            }
+        })
+    });
+    c.bench_function("read_bits_u64 bytes", |b| {
+        b.iter(|| {
+            let mut bs = ByteAtATime(BitReader::new(&data));
+            while let Ok(value) = bs.read_bits_u64(61) {
+                black_box(value);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, parse, write, read_u64);
+criterion_main!(benches);
@@ -0,0 +1,9 @@
+#!/bin/sh
+# Builds the librec core for a target that has no std at all, so anything that
+# still reaches for std (in librec or a dependency) fails to link or compile.
+# Only the rlib is built, a cdylib would want a panic handler.
+set -e
+TARGET=${TARGET:-thumbv7em-none-eabihf}
+cd "$(dirname "$0")"
+rustup target add "$TARGET"
+cargo rustc --lib --no-default-features --target "$TARGET" --crate-type rlib
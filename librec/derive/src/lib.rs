@@ -0,0 +1,340 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Expr, ExprLit, Fields, GenericArgument,
+    Ident, Lit, LitInt, LitStr, Path, PathArguments, Result, Token, Type,
+};
+
+// #[derive(BitCodec)] writes BitCodec::read_from and BitCodec::write_to for a
+// struct, reading and writing its fields in declaration order. Every field is
+// read through BitRead::read_field/written through BitWrite::write_field so
+// dissectors and quantization reports see them by name.
+//
+// Field attributes:
+//   #[bits(10)]                          integer stored in 10 bits
+//   #[bits(6, scale = 1/16, offset = -1)] f64 stored as steps of `scale`
+//   #[optional]                          Option<T> behind a presence flag
+//   #[codec(with = "module")]            module::read / module::write
+//   #[codec(element = "name")]           field name for each array item
+//
+// Arrays like [bool; 6] read and write every item with the field's attributes.
+#[proc_macro_derive(BitCodec, attributes(bits, optional, codec))]
+pub fn derive_bit_codec(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct Bits {
+    count: LitInt,
+    scale: Option<Expr>,
+    offset: Option<Expr>,
+}
+
+impl Parse for Bits {
+    fn parse(input: ParseStream) -> Result<Bits> {
+        let mut bits = Bits {
+            count: input.parse()?,
+            scale: None,
+            offset: None,
+        };
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "scale" => bits.scale = Some(input.parse()?),
+                "offset" => bits.offset = Some(input.parse()?),
+                _ => return Err(Error::new(key.span(), "expected `scale` or `offset`")),
+            }
+        }
+        Ok(bits)
+    }
+}
+
+#[derive(Default)]
+struct Codec {
+    with: Option<Path>,
+    element: Option<LitStr>,
+}
+
+impl Parse for Codec {
+    fn parse(input: ParseStream) -> Result<Codec> {
+        let mut codec = Codec::default();
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let value: LitStr = input.parse()?;
+            match key.to_string().as_str() {
+                "with" => codec.with = Some(value.parse()?),
+                "element" => codec.element = Some(value),
+                _ => return Err(Error::new(key.span(), "expected `with` or `element`")),
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(codec)
+    }
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    bits: Option<Bits>,
+    optional: bool,
+    codec: Codec,
+}
+
+impl FieldAttrs {
+    fn from_attrs(attrs: &[Attribute]) -> Result<FieldAttrs> {
+        let mut field = FieldAttrs::default();
+        for attr in attrs {
+            if attr.path.is_ident("bits") {
+                field.bits = Some(attr.parse_args()?);
+            } else if attr.path.is_ident("optional") {
+                field.optional = true;
+            } else if attr.path.is_ident("codec") {
+                field.codec = attr.parse_args()?;
+            }
+        }
+        Ok(field)
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(&input.ident, "BitCodec needs named fields")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "BitCodec can only be derived for structs")),
+    };
+
+    let mut idents = vec![];
+    let mut names = vec![];
+    let mut reads = vec![];
+    let mut writes = vec![];
+    for field in fields {
+        let ident = field.ident.clone().unwrap();
+        let attrs = FieldAttrs::from_attrs(&field.attrs)?;
+        names.push(ident.to_string());
+        reads.push(read_expr(&field.ty, &attrs)?);
+        writes.push(write_expr(&field.ty, &attrs, quote!(&self.#ident))?);
+        idents.push(ident);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::librec::codec::BitCodec for #name #ty_generics #where_clause {
+            fn read_from<R: ::librec::bit_stream::BitRead>(bs: &mut R) -> ::librec::error::Result<Self> {
+                #(
+                    let #idents = ::librec::bit_stream::BitRead::read_field(bs, #names, |bs| #reads)?;
+                )*
+                Ok(#name { #(#idents),* })
+            }
+
+            #[allow(unused_variables)]
+            fn write_to<W: ::librec::bit_stream::BitWrite>(
+                &self,
+                bs: &mut W,
+                policy: ::librec::quantize::Quantization,
+            ) -> ::librec::error::Result<()> {
+                #(
+                    ::librec::bit_stream::BitWrite::write_field(bs, #names, |bs| #writes)?;
+                )*
+                Ok(())
+            }
+        }
+
+        impl #impl_generics ::librec::field::ToFieldValue for #name #ty_generics #where_clause {
+            fn to_field_value(&self) -> ::librec::field::FieldValue {
+                ::librec::field::FieldValue::Empty
+            }
+        }
+    })
+}
+
+// Expression giving Result<T> for a field of type `ty`, reading from `bs`
+fn read_expr(ty: &Type, attrs: &FieldAttrs) -> Result<TokenStream2> {
+    if let Type::Array(array) = ty {
+        let elem = &array.elem;
+        let len = &array.len;
+        let name = element_name(attrs);
+        let inner = read_expr(elem, attrs)?;
+        return Ok(quote!({
+            let mut items: [#elem; #len] = ::core::default::Default::default();
+            for item in items.iter_mut() {
+                *item = ::librec::bit_stream::BitRead::read_field(bs, #name, |bs| #inner)?;
+            }
+            Ok(items)
+        }));
+    }
+    if attrs.optional {
+        let inner = read_value(option_inner(ty)?, attrs)?;
+        return Ok(quote!(::librec::bit_stream::BitRead::read_optional(bs, |bs| #inner)));
+    }
+    read_value(ty, attrs)
+}
+
+fn read_value(ty: &Type, attrs: &FieldAttrs) -> Result<TokenStream2> {
+    if let Some(with) = &attrs.codec.with {
+        return Ok(quote!(#with::read(bs)));
+    }
+    let prim = primitive(ty);
+    if let Some(bits) = &attrs.bits {
+        let count = &bits.count;
+        if bits.scale.is_some() || bits.offset.is_some() {
+            let (scale, offset) = scale_offset(bits);
+            return Ok(quote!(::librec::bit_stream::BitRead::read_scaled_f64_bits(bs, #count, #scale, #offset)));
+        }
+        return match prim.as_deref() {
+            Some("u8") => Ok(quote!(::librec::bit_stream::BitRead::read_bits_u8(bs, #count))),
+            Some("u16") => Ok(quote!(::librec::bit_stream::BitRead::read_bits_u16(bs, #count))),
+            Some("u32") => Ok(quote!(::librec::bit_stream::BitRead::read_bits_u32(bs, #count))),
+            Some("u64") => Ok(quote!(::librec::bit_stream::BitRead::read_bits_u64(bs, #count))),
+            _ => Err(Error::new_spanned(ty, "#[bits(n)] needs an unsigned integer, or a scale for f64")),
+        };
+    }
+    Ok(match prim.as_deref() {
+        Some("bool") => quote!(::librec::bit_stream::BitRead::read_bool(bs)),
+        Some("u8") => quote!(::librec::bit_stream::BitRead::read_u8(bs)),
+        Some("u16") => quote!(::librec::bit_stream::BitRead::read_u16(bs)),
+        Some("u32") => quote!(::librec::bit_stream::BitRead::read_u32(bs)),
+        Some("u64") => quote!(::librec::bit_stream::BitRead::read_u64(bs)),
+        Some("f32") => quote!(::librec::bit_stream::BitRead::read_f32(bs)),
+        Some("String") => quote!(::librec::bit_stream::BitRead::read_string(bs)),
+        _ => quote!(<#ty as ::librec::codec::BitCodec>::read_from(bs)),
+    })
+}
+
+// Expression giving Result<f64>, the quantization error, after writing
+// `value` (a reference to the field) to `bs`
+fn write_expr(ty: &Type, attrs: &FieldAttrs, value: TokenStream2) -> Result<TokenStream2> {
+    if let Type::Array(array) = ty {
+        let name = element_name(attrs);
+        let inner = write_expr(&array.elem, attrs, quote!(item))?;
+        return Ok(quote!({
+            for item in (#value).iter() {
+                ::librec::bit_stream::BitWrite::write_field(bs, #name, |bs| #inner)?;
+            }
+            Ok(0f64)
+        }));
+    }
+    if attrs.optional {
+        let inner = write_value(option_inner(ty)?, attrs, quote!(value))?;
+        return Ok(quote!(match #value {
+            Some(value) => {
+                ::librec::bit_stream::BitWrite::write_bool(bs, true)?;
+                #inner
+            }
+            None => ::librec::bit_stream::BitWrite::write_bool(bs, false).map(|_| 0f64),
+        }));
+    }
+    write_value(ty, attrs, value)
+}
+
+fn write_value(ty: &Type, attrs: &FieldAttrs, value: TokenStream2) -> Result<TokenStream2> {
+    if let Some(with) = &attrs.codec.with {
+        return Ok(quote!(#with::write(bs, #value, policy)));
+    }
+    let prim = primitive(ty);
+    if let Some(bits) = &attrs.bits {
+        let count = &bits.count;
+        if bits.scale.is_some() || bits.offset.is_some() {
+            let (scale, offset) = scale_offset(bits);
+            return Ok(quote!(::librec::bit_stream::BitWrite::write_quantized_f64_bits(
+                bs, *#value, #count, #scale, #offset, policy
+            )));
+        }
+        let write = match prim.as_deref() {
+            Some("u8") => quote!(::librec::bit_stream::BitWrite::write_bits_u8(bs, *#value, #count)),
+            Some("u16") => quote!(::librec::bit_stream::BitWrite::write_bits_u16(bs, *#value, #count)),
+            Some("u32") => quote!(::librec::bit_stream::BitWrite::write_bits_u32(bs, *#value, #count)),
+            Some("u64") => quote!(::librec::bit_stream::BitWrite::write_bits_u64(bs, *#value, #count)),
+            _ => return Err(Error::new_spanned(ty, "#[bits(n)] needs an unsigned integer, or a scale for f64")),
+        };
+        return Ok(quote!(#write.map(|_| 0f64)));
+    }
+    let write = match prim.as_deref() {
+        Some("bool") => quote!(::librec::bit_stream::BitWrite::write_bool(bs, *#value)),
+        Some("u8") => quote!(::librec::bit_stream::BitWrite::write_u8(bs, *#value)),
+        Some("u16") => quote!(::librec::bit_stream::BitWrite::write_u16(bs, *#value)),
+        Some("u32") => quote!(::librec::bit_stream::BitWrite::write_u32(bs, *#value)),
+        Some("u64") => quote!(::librec::bit_stream::BitWrite::write_u64(bs, *#value)),
+        Some("f32") => quote!(::librec::bit_stream::BitWrite::write_f32(bs, *#value)),
+        Some("String") => quote!(::librec::bit_stream::BitWrite::write_string(bs, (#value).clone())),
+        _ => quote!(::librec::codec::BitCodec::write_to(#value, bs, policy)),
+    };
+    Ok(quote!(#write.map(|_| 0f64)))
+}
+
+fn element_name(attrs: &FieldAttrs) -> String {
+    attrs
+        .codec
+        .element
+        .as_ref()
+        .map_or_else(|| "item".to_string(), |name| name.value())
+}
+
+fn scale_offset(bits: &Bits) -> (TokenStream2, TokenStream2) {
+    let scale = bits.scale.as_ref().map_or_else(|| quote!(1f64), as_f64);
+    let offset = bits.offset.as_ref().map_or_else(|| quote!(0f64), as_f64);
+    (scale, offset)
+}
+
+// Integer literals in scales and offsets are meant as floats, `1/16` should
+// not come out as zero
+fn as_f64(expr: &Expr) -> TokenStream2 {
+    match expr {
+        Expr::Lit(ExprLit { lit: Lit::Int(int), .. }) => quote!((#int as f64)),
+        Expr::Binary(binary) => {
+            let left = as_f64(&binary.left);
+            let right = as_f64(&binary.right);
+            let op = &binary.op;
+            quote!((#left #op #right))
+        }
+        Expr::Unary(unary) => {
+            let inner = as_f64(&unary.expr);
+            let op = &unary.op;
+            quote!((#op #inner))
+        }
+        Expr::Paren(paren) => as_f64(&paren.expr),
+        other => quote!((#other)),
+    }
+}
+
+// Name of a single segment type like `u16` or `bool`
+fn primitive(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() && path.path.segments.len() == 1 => {
+            let segment = &path.path.segments[0];
+            match segment.arguments {
+                PathArguments::None => Some(segment.ident.to_string()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn option_inner(ty: &Type) -> Result<&Type> {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    if let Some(GenericArgument::Type(inner)) = args.args.first() {
+                        return Ok(inner);
+                    }
+                }
+            }
+        }
+    }
+    Err(Error::new_spanned(ty, "#[optional] fields need to be an Option"))
+}
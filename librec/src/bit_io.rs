@@ -0,0 +1,283 @@
+use std::io::{BufRead, BufReader, ErrorKind as IoErrorKind, Read, Write};
+use std::cmp::min;
+use crate::bit_stream::{BitRead, BitWrite};
+use crate::error::{Error, Result};
+use crate::error::ErrorKind::{GenericError, ReadEof};
+
+// Pulls bits straight out of any buffered reader, one byte at a time, so recs
+// can be decoded from pipes, archives or sockets without loading them first.
+// Failures of the reader itself come back as `ErrorKind::Io`, running out of
+// data is the same `ReadEof` error the in-memory streams give.
+pub struct IoBitReader<R> {
+    inner: R,
+    current: u8,
+    // Bits of `current` already used up, 8 means we need a new byte
+    bit_offset: u8,
+    // Error hit while peeking in `eof`, handed out on the next read
+    pending: Option<Error>,
+}
+
+impl<R: Read> IoBitReader<BufReader<R>> {
+    pub fn from_reader(inner: R) -> IoBitReader<BufReader<R>> {
+        IoBitReader::new(BufReader::new(inner))
+    }
+}
+
+impl<R: BufRead> IoBitReader<R> {
+    pub fn new(inner: R) -> IoBitReader<R> {
+        IoBitReader {
+            inner,
+            current: 0,
+            bit_offset: 8,
+            pending: None,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>> {
+        if let Some(error) = self.pending.take() {
+            return Err(error);
+        }
+        let byte = loop {
+            match self.inner.fill_buf() {
+                Ok(buf) => break buf.first().cloned(),
+                Err(ref e) if e.kind() == IoErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        if byte.is_some() {
+            self.inner.consume(1);
+        }
+        Ok(byte)
+    }
+
+    // Make sure `current` has unread bits in it, false if the reader is done
+    fn fill(&mut self) -> Result<bool> {
+        if self.bit_offset < 8 {
+            return Ok(true);
+        }
+        match self.next_byte()? {
+            Some(byte) => {
+                self.current = byte;
+                self.bit_offset = 0;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl<R: BufRead> BitRead for IoBitReader<R> {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        //Sanity
+        if bits > 8 {
+            return Err(GenericError("Reading too many bits").into());
+        }
+
+        let mut result = 0u8;
+        let mut read = 0u8;
+        let (current, bit_offset) = (self.current, self.bit_offset);
+        while read < bits {
+            if !self.fill()? {
+                // Nothing new came out of the reader, so putting the current
+                // byte back undoes the read like it never happened
+                self.current = current;
+                self.bit_offset = bit_offset;
+                return Err(ReadEof.into());
+            }
+            //Take as much as we can out of the current byte
+            let take = min(bits - read, 8 - self.bit_offset);
+            let part = (self.current >> self.bit_offset) & (0xFF >> (8 - take));
+            result |= part << read;
+            read += take;
+            self.bit_offset += take;
+        }
+        Ok(result)
+    }
+
+    fn eof(&mut self) -> bool {
+        match self.fill() {
+            Ok(more) => !more,
+            Err(e) => {
+                // Don't lose the error, the next read will report it
+                self.pending = Some(e);
+                false
+            }
+        }
+    }
+}
+
+// Pushes bits into any writer as soon as each byte is complete. Call `finish`
+// at the end, otherwise a trailing partial byte is never written.
+pub struct IoBitWriter<W: Write> {
+    inner: W,
+    current: u8,
+    bit_offset: u8,
+}
+
+impl<W: Write> IoBitWriter<W> {
+    pub fn new(inner: W) -> IoBitWriter<W> {
+        IoBitWriter {
+            inner,
+            current: 0,
+            bit_offset: 0,
+        }
+    }
+
+    // Pad out the last byte with zeroes (same as BitStream::bytes would give),
+    // flush and hand back the writer
+    pub fn finish(mut self) -> Result<W> {
+        if self.bit_offset > 0 {
+            self.inner.write_all(&[self.current])?;
+            self.current = 0;
+            self.bit_offset = 0;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> BitWrite for IoBitWriter<W> {
+    fn write_bits_u8(&mut self, value: u8, bits: u8) -> Result<()> {
+        //Sanity checking
+        if bits > 8 {
+            return Err(GenericError("Writing too many bits").into());
+        }
+        if !(bits == 8 || value < (1 << bits)) {
+            return Err(GenericError("Value overflows bit count").into());
+        }
+
+        let mut written = 0u8;
+        while written < bits {
+            //Fill whatever is left of the current byte
+            let take = min(bits - written, 8 - self.bit_offset);
+            let part = (value >> written) & (0xFF >> (8 - take));
+            self.current |= part << self.bit_offset;
+            written += take;
+            self.bit_offset += take;
+
+            if self.bit_offset == 8 {
+                self.inner.write_all(&[self.current])?;
+                self.current = 0;
+                self.bit_offset = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Error as IoError, Result as IoResult};
+    use crate::bit_stream::{BitReader, BitWriter};
+
+    // Hands out `data`, then fails instead of reporting the end
+    struct Broken {
+        data: Vec<u8>,
+    }
+
+    impl Read for Broken {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            if self.data.is_empty() {
+                return Err(IoError::other("broken pipe"));
+            }
+            let len = min(buf.len(), self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data.drain(..len);
+            Ok(len)
+        }
+    }
+
+    impl Write for Broken {
+        fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+            if self.data.len() >= 2 {
+                return Err(IoError::other("disk full"));
+            }
+            self.data.push(buf[0]);
+            Ok(1)
+        }
+
+        fn flush(&mut self) -> IoResult<()> {
+            Ok(())
+        }
+    }
+
+    fn write_mixed<W: BitWrite>(bs: &mut W) -> Result<()> {
+        bs.write_bits_u8(0b101, 3)?;
+        bs.write_u16(0xBEEF)?;
+        bs.write_bits_u8(1, 1)?;
+        bs.write_bits_u64(0x1_2345_6789, 37)?;
+        bs.write_bits_u8(0b10, 2)
+    }
+
+    #[test]
+    fn mixed_widths_match_the_in_memory_streams() {
+        let mut memory = BitWriter::new();
+        write_mixed(&mut memory).unwrap();
+        let expected = memory.bytes();
+
+        let mut bs = IoBitWriter::new(Vec::new());
+        write_mixed(&mut bs).unwrap();
+        let bytes = bs.finish().unwrap();
+        assert_eq!(bytes, expected);
+
+        let mut bs = IoBitReader::new(&bytes[..]);
+        assert_eq!(bs.read_bits_u8(3).unwrap(), 0b101);
+        assert_eq!(bs.read_u16().unwrap(), 0xBEEF);
+        assert_eq!(bs.read_bits_u8(1).unwrap(), 1);
+        assert_eq!(bs.read_bits_u64(37).unwrap(), 0x1_2345_6789);
+        assert_eq!(bs.read_bits_u8(2).unwrap(), 0b10);
+        assert_eq!(BitReader::new(&bytes).read_bits_u64(19).unwrap(), 0x5_F77D);
+    }
+
+    #[test]
+    fn finish_writes_the_partial_last_byte() {
+        let mut bs = IoBitWriter::new(Vec::new());
+        bs.write_u8(0xAA).unwrap();
+        bs.write_bits_u8(0b11, 2).unwrap();
+        assert_eq!(bs.inner, [0xAA]);
+        assert_eq!(bs.finish().unwrap(), [0xAA, 0b11]);
+
+        // Nothing extra on a byte boundary
+        let mut bs = IoBitWriter::new(Vec::new());
+        bs.write_u8(0xAA).unwrap();
+        assert_eq!(bs.finish().unwrap(), [0xAA]);
+    }
+
+    #[test]
+    fn running_out_is_eof_and_undoes_the_read() {
+        let mut bs = IoBitReader::new(&[0xF0][..]);
+        assert_eq!(bs.read_bits_u8(4).unwrap(), 0);
+        assert!(!bs.eof());
+        let error = bs.read_bits_u8(5).unwrap_err();
+        assert!(error.is_eof() && !error.is_io());
+        assert_eq!(bs.read_bits_u8(4).unwrap(), 0xF);
+        assert!(bs.eof());
+    }
+
+    #[test]
+    fn io_errors_mid_stream_are_io() {
+        let mut bs = IoBitReader::from_reader(Broken { data: vec![0x34, 0x12] });
+        assert_eq!(bs.read_bits_u8(4).unwrap(), 4);
+        assert_eq!(bs.read_bits_u8(8).unwrap(), 0x23);
+        let error = bs.read_bits_u8(8).unwrap_err();
+        assert!(error.is_io() && !error.is_eof());
+
+        // An error found by `eof` waits for the next read
+        let mut bs = IoBitReader::from_reader(Broken { data: vec![0xFF] });
+        bs.read_u8().unwrap();
+        assert!(!bs.eof());
+        assert!(bs.read_bits_u8(1).unwrap_err().is_io());
+
+        // Two bytes go through, the third fails as soon as it is complete
+        let mut bs = IoBitWriter::new(Broken { data: vec![] });
+        bs.write_u16(0xBEEF).unwrap();
+        bs.write_bits_u8(0x7F, 7).unwrap();
+        assert!(bs.write_bits_u8(1, 1).unwrap_err().is_io());
+        assert_eq!(bs.inner.data, [0xEF, 0xBE]);
+    }
+}
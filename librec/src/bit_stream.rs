@@ -1,280 +1,503 @@
-use std::cmp::min;
-use crate::error::Result;
-use crate::error::ErrorKind::GenericError;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+use core::f64::consts::PI;
+use core::result::Result as StdResult;
+use crate::math;
+use crate::error::{Error, Result};
+use crate::error::ErrorKind::{GenericError, ReadEof};
+use crate::field::{FieldValue, ToFieldValue};
+use crate::encoding::Encoding;
+use crate::huffman::{self, StringCodec};
+use crate::quantize::Quantization;
 
-pub struct BitStream {
-    data: Vec<u8>,
-    bit_offset: u8,
-    byte_offset: usize,
+// Where the next bit will be read from or written to. Bits are packed LSB
+// first, the same way Torque's BitStream does it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BitPosition {
+    pub byte_offset: usize,
+    pub bit_offset: u8,
 }
 
-#[allow(dead_code)]
-impl BitStream {
-    pub fn new(data: Vec<u8>) -> BitStream {
-        BitStream {
-            data,
-            bit_offset: 0,
-            byte_offset: 0,
-        }
-    }
+// Saved position from `mark`, give it back to `reset_to` to rewind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mark(BitPosition);
 
-    pub fn eof(&self) -> bool {
-        self.byte_offset >= self.data.len()
+impl BitPosition {
+    pub fn new(byte_offset: usize, bit_offset: u8) -> BitPosition {
+        BitPosition {
+            byte_offset,
+            bit_offset,
+        }
     }
 
-    pub fn bytes(self) -> Vec<u8> {
-        self.data
+    pub fn from_bits(bits: usize) -> BitPosition {
+        BitPosition {
+            byte_offset: bits / 8,
+            bit_offset: (bits % 8) as u8,
+        }
     }
 
-    pub fn seek(&mut self, byte_offset: usize, bit_offset: u8) {
-        self.byte_offset = byte_offset;
-        self.bit_offset = bit_offset;
+    pub fn bits(&self) -> usize {
+        self.byte_offset * 8 + self.bit_offset as usize
     }
 
-    pub fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+    // Takes the whole value out of one 64 bit load, plus one more byte if it
+    // doesn't start on a byte boundary.
+    fn read_bits(&mut self, data: &[u8], bit_len: usize, bits: u8) -> Result<u64> {
         //Sanity
-        if bits > 8 {
+        if bits > 64 {
             return Err(GenericError("Reading too many bits").into());
         }
-        //EOF
-        if self.byte_offset >= self.data.len()
-            || self.byte_offset == self.data.len() - 1 && self.bit_offset + bits > 8
-        {
-            return Err(GenericError("Read EOF").into());
-        }
-
-        let mut result: u8;
-        //If this value is going to push us onto the next item we need to do
-        // some extra fun math.
-        if self.bit_offset + bits >= 8 {
-            //How many bits over 8 are we going to need?
-            let extra = (self.bit_offset + bits) % 8;
-            //How many bits do we have left before 8?
-            let remain = bits - extra;
-
-            //Get the first, lower, part of the number, should be stored at the
-            // end of the current top. Shift it over so it's in the correct bit
-            let first = self.data[self.byte_offset] >> self.bit_offset;
-            //Add it to the result
-            result = first;
-            //Pop the top off because we've used all its bits
-            self.byte_offset += 1;
-
-            //If we hit 8 exactly then this will just be extra wasted time. Optimize
-            // it out unless we need it.
-            if extra > 0 {
-                //Get the second, upper, part of the number from the new top and
-                // shift it over so it lines up
-                let second = (self.data[self.byte_offset] & (0xFF >> (8 - extra))) << remain;
-                //Or it with the result so we get the final value
-                result |= second;
-            }
-            //Shift should become however many bits we read from that new top
-            self.bit_offset = extra;
-        } else {
-            //We're not popping anything off so we can just grab the bits from
-            // the top and have a nice day.
-            result = (self.data[self.byte_offset] >> self.bit_offset) & (0xFF >> (8 - bits));
+        //EOF, down to the bit
+        if self.bits() + bits as usize > bit_len {
+            return Err(ReadEof.into());
+        }
+        if bits == 0 {
+            return Ok(0);
+        }
 
-            //Just add to the shift
-            self.bit_offset += bits;
+        let mut result = load_word(data, self.byte_offset) >> self.bit_offset;
+        //Bits that did not fit in the word are at the start of the ninth byte
+        let loaded = 64 - self.bit_offset;
+        if bits > loaded {
+            result |= u64::from(data[self.byte_offset + 8]) << loaded;
         }
+        if bits < 64 {
+            result &= (1u64 << bits) - 1;
+        }
+
+        *self = BitPosition::from_bits(self.bits() + bits as usize);
         Ok(result)
     }
 
-    pub fn write_bits_u8(&mut self, value: u8, bits: u8) -> Result<()> {
+    // The value is shifted into place once and OR'd onto every byte it covers
+    fn write_bits(&mut self, data: &mut Vec<u8>, bit_len: &mut usize, value: u64, bits: u8) -> Result<()> {
         //Sanity checking
-        if bits > 8 {
+        if bits > 64 {
             return Err(GenericError("Writing too many bits").into());
         }
-        if !(bits == 8 || value < (1 << bits)) {
+        if bits < 64 && value >> bits != 0 {
             return Err(GenericError("Value overflows bit count").into());
         }
+        if bits == 0 {
+            return Ok(());
+        }
 
-        //Sanitize value, don't let it be longer than the number of bits we're promised
-        let san_value = value & (0xFF >> (8 - bits));
+        let end = self.bits() + bits as usize;
+        let end_byte = end.div_ceil(8);
+        if data.len() < end_byte {
+            data.resize(end_byte, 0);
+        }
 
-        if self.data.len() <= self.byte_offset {
-            self.data.resize(self.byte_offset + 1, 0);
+        //Shifted so it lines up with the next open bit, at most 9 bytes long
+        let shifted = (u128::from(value) << self.bit_offset).to_le_bytes();
+        for (byte, shifted_byte) in data[self.byte_offset..end_byte].iter_mut().zip(shifted.iter()) {
+            *byte |= shifted_byte;
         }
 
-        let last = self.data.get_mut(self.byte_offset).ok_or(GenericError("Cannot get mutable data"))?;
+        *self = BitPosition::from_bits(end);
+        *bit_len = max(*bit_len, end);
+        Ok(())
+    }
+}
 
-        //If this value is going to push us onto the next item we need to do
-        // some extra fun math.
-        if self.bit_offset + bits >= 8 {
-            //How many bits over 8 are we going to need?
-            let extra = (self.bit_offset + bits) % 8;
-            //How many bits do we have left before 8?
-            let remain = bits - extra;
+// Little endian word starting at `offset`, zero filled past the end of `data`
+fn load_word(data: &[u8], offset: usize) -> u64 {
+    if let Some(bytes) = data.get(offset..offset + 8) {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(bytes);
+        return u64::from_le_bytes(word);
+    }
+    let mut word = [0u8; 8];
+    let available = &data[min(offset, data.len())..];
+    let len = min(available.len(), 8);
+    word[..len].copy_from_slice(&available[..len]);
+    u64::from_le_bytes(word)
+}
 
-            //Get the part of the value that will be pushed onto the current top,
-            // should be `remain` bits long.
-            let first = san_value & (0xFF >> (8 - remain));
-            //Push it on and make sure we start at the next open bit
-            *last |= first << self.bit_offset;
+// Bit widths for each of the packed compressed point types
+const COMPRESSED_POINT_BITS: [u8; 3] = [16, 18, 20];
 
-            if extra > 0 {
-                //Get the second part of the value that will become the next top, should
-                // be `extra` bits long.
-                let second = (san_value >> remain) & (0xFF >> (8 - extra));
-                //Start a new top with it
-                self.data.push(second);
-            }
+// getBinLog2(getNextPow2(end - start + 1))
+fn ranged_bits(start: u32, end: u32) -> u8 {
+    let size = u64::from(end - start) + 1;
+    (64 - (size - 1).leading_zeros()) as u8
+}
 
-            self.byte_offset += 1;
+// Signed ints need a bit for the sign and floats need one to divide by, and
+// all of them go through 32 bit ints
+fn check_bits(bits: u8) -> Result<()> {
+    if bits == 0 || bits > 32 {
+        return Err(GenericError("Bit count out of range").into());
+    }
+    Ok(())
+}
 
-            //Shift should become however many bits long that new top is
-            self.bit_offset = extra;
-        } else {
-            //We don't have to create a new top, we can just slap this one on the
-            // end of the original one. OR the bits on, make sure to push them over
-            // so they line up, and cut off anything at the end
-            *last |= (san_value << self.bit_offset) & (0xFF >> (8 - bits - self.bit_offset));
+// (1 << bits) - 1, the denominator Torque uses for its normalized floats
+fn float_max(bits: u8) -> u32 {
+    ((1u64 << bits) - 1) as u32
+}
+
+// mAtan/mSin/mCos all go through double and back
+fn torque_atan(x: f32, y: f32) -> f32 {
+    math::atan2(f64::from(x), f64::from(y)) as f32
+}
+
+fn torque_sin(value: f32) -> f32 {
+    math::sin(f64::from(value)) as f32
+}
+
+fn torque_cos(value: f32) -> f32 {
+    math::cos(f64::from(value)) as f32
+}
+
+// Anything that bits can be pulled out of. Implementors only need to provide
+// `read_bits_u8` and `eof`, everything else is built on top of those.
+pub trait BitRead {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8>;
+
+    fn eof(&mut self) -> bool;
+
+    // Hooks for tools that want to know which field every bit belongs to, see
+    // dissect::Dissector. Regular readers just ignore them.
+    fn begin_field(&mut self, _name: &'static str) {}
+
+    fn end_field(&mut self, _value: StdResult<FieldValue, &Error>) {}
+
+    // Read something as one named field, which can have fields of its own
+    fn read_field<T, F>(&mut self, name: &'static str, read_fn: F) -> Result<T>
+    where
+        T: ToFieldValue,
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        self.begin_field(name);
+        let result = read_fn(self);
+        self.end_field(result.as_ref().map(|value| value.to_field_value()));
+        result
+    }
 
-            //Just add to the shift
-            self.bit_offset += bits;
+    // Read up to 64 bits at once. Streams that can do better than a byte at a
+    // time should override this, all the wider reads go through it.
+    fn read_bits(&mut self, bits: u8) -> Result<u64> {
+        if bits > 64 {
+            return Err(GenericError("Reading too many bits").into());
         }
-        Ok(())
+        let mut result = 0u64;
+        let mut done = 0;
+        while done < bits {
+            let chunk = min(bits - done, 8);
+            result |= u64::from(self.read_bits_u8(chunk)?) << done;
+            done += chunk;
+        }
+        Ok(result)
     }
 
-    pub fn read_bits_u16(&mut self, bits: u8) -> Result<u16> {
-        let lower = self.read_bits_u8(min(bits, 8))?;
-        if bits <= 8 {
-            Ok(u16::from(lower))
-        } else {
-            let upper = self.read_bits_u8(bits - 8)?;
-            Ok(u16::from(lower) | ((u16::from(upper)) << 8u16))
+    fn read_bits_u16(&mut self, bits: u8) -> Result<u16> {
+        if bits > 16 {
+            return Err(GenericError("Reading too many bits").into());
         }
+        Ok(self.read_bits(bits)? as u16)
     }
 
-    pub fn read_bits_u32(&mut self, bits: u8) -> Result<u32> {
-        let lower = self.read_bits_u16(min(bits, 16))?;
-        if bits <= 16 {
-            Ok(u32::from(lower))
-        } else {
-            let upper = self.read_bits_u16(bits - 16)?;
-            Ok(u32::from(lower) | ((u32::from(upper)) << 16u32))
+    fn read_bits_u32(&mut self, bits: u8) -> Result<u32> {
+        if bits > 32 {
+            return Err(GenericError("Reading too many bits").into());
         }
+        Ok(self.read_bits(bits)? as u32)
     }
 
-    pub fn read_bits_u64(&mut self, bits: u8) -> Result<u64> {
-        let lower = self.read_bits_u32(min(bits, 32))?;
-        if bits <= 32 {
-            Ok(u64::from(lower))
-        } else {
-            let upper = self.read_bits_u32(bits - 32)?;
-            Ok(u64::from(lower) | ((u64::from(upper)) << 32u64))
+    fn read_bits_u64(&mut self, bits: u8) -> Result<u64> {
+        self.read_bits(bits)
+    }
+
+    fn read_bool(&mut self) -> Result<bool> {
+        Ok(self.read_bits_u8(1)? == 1)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        self.read_bits_u8(8)
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        self.read_bits_u16(16)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        self.read_bits_u32(32)
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        self.read_bits_u64(64)
+    }
+
+    fn read_string_bytes(&mut self) -> Result<Vec<u8>> {
+        let length = self.read_bits_u8(8)?;
+        let mut bytes: Vec<u8> = Vec::with_capacity(length as usize);
+        for _ in 0..length {
+            bytes.push(self.read_bits_u8(8)?);
         }
+        Ok(bytes)
     }
 
-    pub fn write_bits_u16(&mut self, value: u16, bits: u8) -> Result<()> {
-        if !(bits == 16 || value < (1 << u16::from(bits))) {
-            return Err(GenericError("Value overflows bit count").into());
+    // Strings are decoded with whatever code page they look like they are in,
+    // see Encoding::detect
+    fn read_string(&mut self) -> Result<String> {
+        let bytes = self.read_string_bytes()?;
+        Ok(Encoding::detect_and_decode(&bytes)?.0)
+    }
+
+    fn read_huffman_string(&mut self) -> Result<String> {
+        let bytes = huffman::read_huffman_bytes(self)?;
+        Ok(Encoding::detect_and_decode(&bytes)?.0)
+    }
+
+    // Torque's readString on a stream with a stringBuffer, `buffer` holds the
+    // previous string and is updated to this one
+    fn read_buffered_string(&mut self, buffer: &mut Vec<u8>) -> Result<String> {
+        let bytes = huffman::read_buffered_bytes(self, buffer)?;
+        Ok(Encoding::detect_and_decode(&bytes)?.0)
+    }
+
+    fn read_string_bytes_with(&mut self, codec: StringCodec) -> Result<Vec<u8>> {
+        match codec {
+            StringCodec::Raw => self.read_string_bytes(),
+            StringCodec::Huffman => huffman::read_huffman_bytes(self),
         }
-        self.write_bits_u8((value & 0xFF) as u8, min(bits, 8))?;
-        if bits <= 8 {
-            Ok(())
+    }
+
+    fn read_string_with(&mut self, codec: StringCodec) -> Result<String> {
+        Ok(self.read_encoded_string_with(codec)?.0)
+    }
+
+    // Also gives back the detected code page so the string can be written
+    // back out the same way
+    fn read_encoded_string_with(&mut self, codec: StringCodec) -> Result<(String, Encoding)> {
+        let bytes = self.read_string_bytes_with(codec)?;
+        Encoding::detect_and_decode(&bytes)
+    }
+
+    fn read_optional<T, F>(&mut self, read_fn: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if self.read_field("present", |bs| bs.read_bool())? {
+            Ok(Some(read_fn(self)?))
         } else {
-            self.write_bits_u8((value >> 8u16) as u8, bits - 8)
+            Ok(None)
         }
     }
 
-    pub fn write_bits_u32(&mut self, value: u32, bits: u8) -> Result<()> {
-        if !(bits == 32 || value < (1 << u32::from(bits))) {
-            return Err(GenericError("Value overflows bit count").into());
+    fn read_scaled_f64_bits(&mut self, bits: u8, scale: f64, offset: f64) -> Result<f64> {
+        let inner = self.read_bits_u64(bits)? as f64;
+        Ok(inner * scale + offset)
+    }
+
+    // The rest of these mirror Torque's BitStream so its other formats can be
+    // decoded bit for bit. Torque does all of this in F32, so we do too.
+
+    fn read_flag(&mut self) -> Result<bool> {
+        self.read_bool()
+    }
+
+    // readInt, which is allowed to read nothing at all
+    fn read_int(&mut self, bits: u8) -> Result<u32> {
+        if bits == 0 {
+            return Ok(0);
         }
-        self.write_bits_u16((value & 0xFF_FF) as u16, min(bits, 16))?;
-        if bits <= 16 {
-            Ok(())
+        self.read_bits_u32(bits)
+    }
+
+    fn read_signed_int(&mut self, bits: u8) -> Result<i32> {
+        check_bits(bits)?;
+        let negative = self.read_flag()?;
+        let magnitude = self.read_int(bits - 1)? as i32;
+        if negative {
+            Ok(-magnitude)
         } else {
-            self.write_bits_u16((value >> 16u32) as u16, bits - 16)
+            Ok(magnitude)
         }
     }
 
-    pub fn write_bits_u64(&mut self, value: u64, bits: u8) -> Result<()> {
-        if !(bits == 64 || value < (1 << u64::from(bits))) {
-            return Err(GenericError("Value overflows bit count").into());
+    fn read_ranged_u32(&mut self, start: u32, end: u32) -> Result<u32> {
+        if end < start {
+            return Err(GenericError("Range end is before range start").into());
         }
-        self.write_bits_u32((value & 0xFF_FF_FF) as u32, min(bits, 32))?;
-        if bits <= 32 {
-            Ok(())
+        let value = self.read_int(ranged_bits(start, end))?;
+        Ok(value.wrapping_add(start))
+    }
+
+    fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    // [0, 1] in `bits` bits
+    fn read_float(&mut self, bits: u8) -> Result<f32> {
+        check_bits(bits)?;
+        let value = self.read_int(bits)?;
+        Ok(value as f32 / float_max(bits) as f32)
+    }
+
+    // [-1, 1] in `bits` bits
+    fn read_signed_float(&mut self, bits: u8) -> Result<f32> {
+        check_bits(bits)?;
+        let value = self.read_int(bits)? as i32;
+        Ok(value.wrapping_mul(2) as f32 / float_max(bits) as f32 - 1.0f32)
+    }
+
+    fn read_normal_vector(&mut self, bits: u8) -> Result<[f32; 3]> {
+        check_bits(bits)?;
+        let phi = (f64::from(self.read_signed_float(bits + 1)?) * PI) as f32;
+        let theta = (f64::from(self.read_signed_float(bits)?) * (PI / 2.0)) as f32;
+
+        Ok([
+            torque_sin(phi) * torque_cos(theta),
+            torque_cos(phi) * torque_cos(theta),
+            torque_sin(theta),
+        ])
+    }
+
+    // Torque keeps the compression point on the stream, we just pass it in
+    fn read_compressed_point(&mut self, origin: [f32; 3], scale: f32) -> Result<[f32; 3]> {
+        let kind = self.read_int(2)? as usize;
+        if kind == 3 {
+            Ok([self.read_f32()?, self.read_f32()?, self.read_f32()?])
         } else {
-            self.write_bits_u32((value >> 32u64) as u32, bits - 32)
+            let bits = COMPRESSED_POINT_BITS[kind];
+            let mut point = [0f32; 3];
+            for (axis, value) in point.iter_mut().enumerate() {
+                let offset = self.read_signed_int(bits)? as f32;
+                *value = origin[axis] + offset * scale;
+            }
+            Ok(point)
         }
     }
+}
 
-    pub fn read_bool(&mut self) -> Result<bool> {
-        Ok(self.read_bits_u8(1)? == 1)
+// Anything that bits can be pushed into. Implementors only need to provide
+// `write_bits_u8`, everything else is built on top of it.
+pub trait BitWrite {
+    fn write_bits_u8(&mut self, value: u8, bits: u8) -> Result<()>;
+
+    // Hooks for tools that want to know what was written per field, along
+    // with how far off the written value is. Regular writers just ignore them.
+    fn begin_field(&mut self, _name: &'static str) {}
+
+    fn end_field(&mut self, _error: StdResult<f64, &Error>) {}
+
+    // Write something as one named field, `write_fn` gives back the
+    // quantization error (0 for anything that is written exactly)
+    fn write_field<F>(&mut self, name: &'static str, write_fn: F) -> Result<f64>
+    where
+        F: FnOnce(&mut Self) -> Result<f64>,
+    {
+        self.begin_field(name);
+        let result = write_fn(self);
+        self.end_field(result.as_ref().copied());
+        result
     }
 
-    pub fn read_u8(&mut self) -> Result<u8> {
-        self.read_bits_u8(8)
+    // Write up to 64 bits at once. Streams that can do better than a byte at a
+    // time should override this, all the wider writes go through it.
+    fn write_bits(&mut self, value: u64, bits: u8) -> Result<()> {
+        if bits > 64 {
+            return Err(GenericError("Writing too many bits").into());
+        }
+        if bits < 64 && value >> bits != 0 {
+            return Err(GenericError("Value overflows bit count").into());
+        }
+        let mut done = 0;
+        while done < bits {
+            let chunk = min(bits - done, 8);
+            self.write_bits_u8((value >> done) as u8 & (0xFF >> (8 - chunk)), chunk)?;
+            done += chunk;
+        }
+        Ok(())
     }
 
-    pub fn read_u16(&mut self) -> Result<u16> {
-        self.read_bits_u16(16)
+    fn write_bits_u16(&mut self, value: u16, bits: u8) -> Result<()> {
+        if bits > 16 {
+            return Err(GenericError("Writing too many bits").into());
+        }
+        self.write_bits(u64::from(value), bits)
     }
 
-    pub fn read_u32(&mut self) -> Result<u32> {
-        self.read_bits_u32(32)
+    fn write_bits_u32(&mut self, value: u32, bits: u8) -> Result<()> {
+        if bits > 32 {
+            return Err(GenericError("Writing too many bits").into());
+        }
+        self.write_bits(u64::from(value), bits)
     }
 
-    pub fn read_u64(&mut self) -> Result<u64> {
-        self.read_bits_u64(64)
+    fn write_bits_u64(&mut self, value: u64, bits: u8) -> Result<()> {
+        if bits < 64 && value >> bits != 0 {
+            return Err(GenericError("Value overflows bit count").into());
+        }
+        self.write_bits(value, bits)
     }
 
-    pub fn write_bool(&mut self, value: bool) -> Result<()> {
+    fn write_bool(&mut self, value: bool) -> Result<()> {
         self.write_bits_u8(value as u8, 1)
     }
 
-    pub fn write_u8(&mut self, value: u8) -> Result<()> {
+    fn write_u8(&mut self, value: u8) -> Result<()> {
         self.write_bits_u8(value, 8)
     }
 
-    pub fn write_u16(&mut self, value: u16) -> Result<()> {
+    fn write_u16(&mut self, value: u16) -> Result<()> {
         self.write_bits_u16(value, 16)
     }
 
-    pub fn write_u32(&mut self, value: u32) -> Result<()> {
+    fn write_u32(&mut self, value: u32) -> Result<()> {
         self.write_bits_u32(value, 32)
     }
 
-    pub fn write_u64(&mut self, value: u64) -> Result<()> {
+    fn write_u64(&mut self, value: u64) -> Result<()> {
         self.write_bits_u64(value, 64)
     }
 
-    pub fn read_string(&mut self) -> Result<String> {
-        let length = self.read_bits_u8(8)?;
-        let mut bytes: Vec<u8> = Vec::with_capacity(length as usize);
-        for _ in 0..length {
-            bytes.push(self.read_bits_u8(8)?);
+    fn write_string_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        //The length has to fit in a byte
+        if bytes.len() > 255 {
+            return Err(huffman::too_long());
         }
-        String::from_utf8(bytes).map_err(|e| e.into())
-    }
-
-    pub fn write_string(&mut self, value: String) -> Result<()> {
-        self.write_bits_u8(value.len() as u8, 8)?;
-        for ch in value.into_bytes() {
+        self.write_bits_u8(bytes.len() as u8, 8)?;
+        for &ch in bytes {
             self.write_bits_u8(ch, 8)?;
         }
         Ok(())
     }
 
-    pub fn read_optional<T, F>(&mut self, read_fn: F) -> Result<Option<T>>
-    where
-        F: FnOnce(&mut BitStream) -> Result<T>,
-    {
-        if self.read_bool()? {
-            Ok(Some(read_fn(self)?))
-        } else {
-            Ok(None)
+    fn write_string(&mut self, value: String) -> Result<()> {
+        self.write_string_bytes(value.as_bytes())
+    }
+
+    fn write_huffman_string(&mut self, value: String) -> Result<()> {
+        huffman::write_huffman_bytes(self, value.as_bytes())
+    }
+
+    fn write_buffered_string(&mut self, value: String, buffer: &mut Vec<u8>) -> Result<()> {
+        huffman::write_buffered_bytes(self, value.as_bytes(), buffer)
+    }
+
+    fn write_string_bytes_with(&mut self, bytes: &[u8], codec: StringCodec) -> Result<()> {
+        match codec {
+            StringCodec::Raw => self.write_string_bytes(bytes),
+            StringCodec::Huffman => huffman::write_huffman_bytes(self, bytes),
         }
     }
 
-    pub fn write_optional<T, F>(&mut self, value: Option<T>, write_fn: F) -> Result<()>
+    fn write_string_with(&mut self, value: String, codec: StringCodec) -> Result<()> {
+        self.write_string_bytes_with(value.as_bytes(), codec)
+    }
+
+    fn write_encoded_string_with(&mut self, value: &str, encoding: Encoding, codec: StringCodec) -> Result<()> {
+        self.write_string_bytes_with(&encoding.encode(value)?, codec)
+    }
+
+    fn write_optional<T, F>(&mut self, value: Option<T>, write_fn: F) -> Result<()>
     where
-        F: Fn(&mut BitStream, T) -> Result<()>,
+        F: FnOnce(&mut Self, T) -> Result<()>,
     {
         match value {
             Some(val) => {
@@ -285,22 +508,800 @@ impl BitStream {
         }
     }
 
-    pub fn read_scaled_f64_bits(&mut self, bits: u8, scale: f64, offset: f64) -> Result<f64> {
-        let inner = self.read_bits_u64(bits)? as f64;
-        Ok(inner * scale + offset)
+    fn write_scaled_f64_bits(
+        &mut self,
+        value: f64,
+        bits: u8,
+        scale: f64,
+        offset: f64,
+    ) -> Result<()> {
+        self.write_quantized_f64_bits(value, bits, scale, offset, Quantization::default())?;
+        Ok(())
     }
 
-    pub fn write_scaled_f64_bits(
+    // Same as write_scaled_f64_bits with a choice of rounding and range
+    // handling. Gives back the quantization error of what was written.
+    fn write_quantized_f64_bits(
         &mut self,
         value: f64,
         bits: u8,
         scale: f64,
         offset: f64,
-    ) -> Result<()> {
-        let scaled = (value - offset) / scale;
-//        if !(scaled < f64::from(bits).exp2()) {
-//            return Err(());
-//        }
-        self.write_bits_u64(scaled as u64, bits)
+        policy: Quantization,
+    ) -> Result<f64> {
+        let quantized = policy.quantize(value, bits, scale, offset)?;
+        self.write_bits_u64(quantized.raw, bits)?;
+        Ok(quantized.error)
+    }
+
+    // Returns the flag so it can be used like Torque's `if (writeFlag(x))`
+    fn write_flag(&mut self, value: bool) -> Result<bool> {
+        self.write_bool(value)?;
+        Ok(value)
+    }
+
+    // writeInt, which is allowed to write nothing at all
+    fn write_int(&mut self, value: u32, bits: u8) -> Result<()> {
+        if bits == 0 {
+            if value != 0 {
+                return Err(GenericError("Value overflows bit count").into());
+            }
+            return Ok(());
+        }
+        self.write_bits_u32(value, bits)
+    }
+
+    // Sign flag followed by the magnitude in `bits - 1` bits
+    fn write_signed_int(&mut self, value: i32, bits: u8) -> Result<()> {
+        check_bits(bits)?;
+        self.write_flag(value < 0)?;
+        self.write_int(value.unsigned_abs(), bits - 1)
+    }
+
+    fn write_ranged_u32(&mut self, value: u32, start: u32, end: u32) -> Result<()> {
+        if end < start {
+            return Err(GenericError("Range end is before range start").into());
+        }
+        if value < start || value > end {
+            return Err(GenericError("Value outside of range").into());
+        }
+        self.write_int(value - start, ranged_bits(start, end))
+    }
+
+    fn write_f32(&mut self, value: f32) -> Result<()> {
+        self.write_u32(value.to_bits())
+    }
+
+    fn write_float(&mut self, value: f32, bits: u8) -> Result<()> {
+        check_bits(bits)?;
+        if !(0f32..=1f32).contains(&value) {
+            return Err(GenericError("Float outside of [0, 1]").into());
+        }
+        self.write_int((value * float_max(bits) as f32) as u32, bits)
+    }
+
+    fn write_signed_float(&mut self, value: f32, bits: u8) -> Result<()> {
+        check_bits(bits)?;
+        if !(-1f32..=1f32).contains(&value) {
+            return Err(GenericError("Float outside of [-1, 1]").into());
+        }
+        // Torque's `.5` here is a double, so the rest happens in double too
+        let scaled = (f64::from(value + 1f32) * 0.5) * float_max(bits) as f64;
+        self.write_int(scaled as u32, bits)
+    }
+
+    fn write_normal_vector(&mut self, vec: [f32; 3], bits: u8) -> Result<()> {
+        check_bits(bits)?;
+        let [x, y, z] = vec;
+        let phi = (f64::from(torque_atan(x, y)) / PI) as f32;
+        let horizontal = math::sqrt(f64::from(x * x + y * y)) as f32;
+        let theta = (f64::from(torque_atan(z, horizontal)) / (PI / 2.0)) as f32;
+
+        self.write_signed_float(phi, bits + 1)?;
+        self.write_signed_float(theta, bits)
+    }
+
+    // Small offsets from `origin` get packed into fewer bits, anything too far
+    // away is written out as raw floats instead
+    fn write_compressed_point(&mut self, point: [f32; 3], origin: [f32; 3], scale: f32) -> Result<()> {
+        let inv_scale = 1f32 / scale;
+        let vec = [point[0] - origin[0], point[1] - origin[1], point[2] - origin[2]];
+        let len = math::sqrt(f64::from(vec[0] * vec[0] + vec[1] * vec[1] + vec[2] * vec[2])) as f32;
+        let dist = len * inv_scale;
+
+        let kind = if dist < (1 << 15) as f32 {
+            0
+        } else if dist < (1 << 17) as f32 {
+            1
+        } else if dist < (1 << 19) as f32 {
+            2
+        } else {
+            3
+        };
+        self.write_int(kind as u32, 2)?;
+
+        if kind == 3 {
+            for &value in &point {
+                self.write_f32(value)?;
+            }
+        } else {
+            let bits = COMPRESSED_POINT_BITS[kind];
+            for &value in &vec {
+                self.write_signed_int((value * inv_scale) as i32, bits)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Reads at most `bits` bits from another reader, for walking through length
+// prefixed blocks in place.
+pub struct BitTake<'a, R: ?Sized> {
+    inner: &'a mut R,
+    remaining: usize,
+    limit_reached: bool,
+}
+
+impl<'a, R: BitRead + ?Sized> BitTake<'a, R> {
+    pub fn new(inner: &'a mut R, bits: usize) -> BitTake<'a, R> {
+        BitTake {
+            inner,
+            remaining: bits,
+            limit_reached: false,
+        }
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        self.remaining
+    }
+
+    // Whether a read failed because it ran past the block rather than past
+    // the end of the underlying reader
+    pub fn limit_reached(&self) -> bool {
+        self.limit_reached
+    }
+
+    // Throw away whatever is left of the block
+    pub fn skip_rest(&mut self) -> Result<()> {
+        while self.remaining > 0 {
+            let bits = min(self.remaining, 8) as u8;
+            self.read_bits_u8(bits)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: BitRead + ?Sized> BitRead for BitTake<'a, R> {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        if bits as usize > self.remaining {
+            self.limit_reached = true;
+            return Err(ReadEof.into());
+        }
+        let value = self.inner.read_bits_u8(bits)?;
+        self.remaining -= bits as usize;
+        Ok(value)
+    }
+
+    fn read_bits(&mut self, bits: u8) -> Result<u64> {
+        if bits as usize > self.remaining {
+            self.limit_reached = true;
+            return Err(ReadEof.into());
+        }
+        let value = self.inner.read_bits(bits)?;
+        self.remaining -= bits as usize;
+        Ok(value)
+    }
+
+    fn eof(&mut self) -> bool {
+        self.remaining == 0 || self.inner.eof()
+    }
+
+    fn begin_field(&mut self, name: &'static str) {
+        self.inner.begin_field(name);
+    }
+
+    fn end_field(&mut self, value: StdResult<FieldValue, &Error>) {
+        self.inner.end_field(value);
+    }
+}
+
+// Counts the bits read through another reader, for readers that can't say
+// where they are themselves
+pub struct BitCounter<R> {
+    inner: R,
+    position: usize,
+}
+
+impl<R: BitRead> BitCounter<R> {
+    pub fn new(inner: R) -> BitCounter<R> {
+        BitCounter { inner, position: 0 }
+    }
+
+    // Bits read so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BitRead> BitRead for BitCounter<R> {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        let value = self.inner.read_bits_u8(bits)?;
+        self.position += bits as usize;
+        Ok(value)
+    }
+
+    fn read_bits(&mut self, bits: u8) -> Result<u64> {
+        let value = self.inner.read_bits(bits)?;
+        self.position += bits as usize;
+        Ok(value)
+    }
+
+    fn eof(&mut self) -> bool {
+        self.inner.eof()
+    }
+
+    fn begin_field(&mut self, name: &'static str) {
+        self.inner.begin_field(name);
+    }
+
+    fn end_field(&mut self, value: StdResult<FieldValue, &Error>) {
+        self.inner.end_field(value);
+    }
+}
+
+// Owned, seekable stream that can be both read from and written to.
+pub struct BitStream {
+    data: Vec<u8>,
+    cursor: BitPosition,
+    // Number of valid bits in `data`, the last byte may only be partly used
+    bit_len: usize,
+}
+
+impl BitStream {
+    pub fn new(data: Vec<u8>) -> BitStream {
+        let bit_len = data.len() * 8;
+        BitStream {
+            data,
+            cursor: BitPosition::default(),
+            bit_len,
+        }
+    }
+
+    pub fn eof(&self) -> bool {
+        self.remaining_bits() == 0
+    }
+
+    pub fn bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn seek(&mut self, byte_offset: usize, bit_offset: u8) {
+        self.cursor = BitPosition::new(byte_offset, bit_offset);
+    }
+
+    pub fn position(&self) -> BitPosition {
+        self.cursor
+    }
+
+    pub fn len_bits(&self) -> usize {
+        self.bit_len
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        self.bit_len.saturating_sub(self.cursor.bits())
+    }
+
+    pub fn mark(&self) -> Mark {
+        Mark(self.cursor)
+    }
+
+    // Only meant for rewinding reads, writes are OR'd into the existing bytes
+    // so writing over the same spot again will not clear it.
+    pub fn reset_to(&mut self, mark: Mark) {
+        self.cursor = mark.0;
+    }
+
+    pub fn peek_bits(&self, bits: u8) -> Result<u64> {
+        BitReader {
+            data: &self.data,
+            cursor: self.cursor,
+            bit_len: self.bit_len,
+        }.read_bits_u64(bits)
+    }
+}
+
+// The methods BitStream had before BitRead and BitWrite, so callers that only
+// import BitStream keep working without bringing the traits into scope
+impl BitStream {
+    pub fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        BitRead::read_bits_u8(self, bits)
+    }
+
+    pub fn write_bits_u8(&mut self, value: u8, bits: u8) -> Result<()> {
+        BitWrite::write_bits_u8(self, value, bits)
+    }
+
+    pub fn read_bits_u16(&mut self, bits: u8) -> Result<u16> {
+        BitRead::read_bits_u16(self, bits)
+    }
+
+    pub fn read_bits_u32(&mut self, bits: u8) -> Result<u32> {
+        BitRead::read_bits_u32(self, bits)
+    }
+
+    pub fn read_bits_u64(&mut self, bits: u8) -> Result<u64> {
+        BitRead::read_bits_u64(self, bits)
+    }
+
+    pub fn write_bits_u16(&mut self, value: u16, bits: u8) -> Result<()> {
+        BitWrite::write_bits_u16(self, value, bits)
+    }
+
+    pub fn write_bits_u32(&mut self, value: u32, bits: u8) -> Result<()> {
+        BitWrite::write_bits_u32(self, value, bits)
+    }
+
+    pub fn write_bits_u64(&mut self, value: u64, bits: u8) -> Result<()> {
+        BitWrite::write_bits_u64(self, value, bits)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        BitRead::read_bool(self)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        BitRead::read_u8(self)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        BitRead::read_u16(self)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        BitRead::read_u32(self)
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        BitRead::read_u64(self)
+    }
+
+    pub fn write_bool(&mut self, value: bool) -> Result<()> {
+        BitWrite::write_bool(self, value)
+    }
+
+    pub fn write_u8(&mut self, value: u8) -> Result<()> {
+        BitWrite::write_u8(self, value)
+    }
+
+    pub fn write_u16(&mut self, value: u16) -> Result<()> {
+        BitWrite::write_u16(self, value)
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> Result<()> {
+        BitWrite::write_u32(self, value)
+    }
+
+    pub fn write_u64(&mut self, value: u64) -> Result<()> {
+        BitWrite::write_u64(self, value)
+    }
+
+    pub fn read_string(&mut self) -> Result<String> {
+        BitRead::read_string(self)
+    }
+
+    pub fn write_string(&mut self, value: String) -> Result<()> {
+        BitWrite::write_string(self, value)
+    }
+
+    pub fn read_optional<T, F>(&mut self, read_fn: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&mut BitStream) -> Result<T>,
+    {
+        BitRead::read_optional(self, read_fn)
+    }
+
+    pub fn write_optional<T, F>(&mut self, value: Option<T>, write_fn: F) -> Result<()>
+    where
+        F: FnOnce(&mut BitStream, T) -> Result<()>,
+    {
+        BitWrite::write_optional(self, value, write_fn)
+    }
+
+    pub fn read_scaled_f64_bits(&mut self, bits: u8, scale: f64, offset: f64) -> Result<f64> {
+        BitRead::read_scaled_f64_bits(self, bits, scale, offset)
+    }
+
+    pub fn write_scaled_f64_bits(&mut self, value: f64, bits: u8, scale: f64, offset: f64) -> Result<()> {
+        BitWrite::write_scaled_f64_bits(self, value, bits, scale, offset)
+    }
+}
+
+impl BitRead for BitStream {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        if bits > 8 {
+            return Err(GenericError("Reading too many bits").into());
+        }
+        Ok(self.cursor.read_bits(&self.data, self.bit_len, bits)? as u8)
+    }
+
+    fn read_bits(&mut self, bits: u8) -> Result<u64> {
+        self.cursor.read_bits(&self.data, self.bit_len, bits)
+    }
+
+    fn eof(&mut self) -> bool {
+        BitStream::eof(self)
+    }
+}
+
+impl BitWrite for BitStream {
+    fn write_bits_u8(&mut self, value: u8, bits: u8) -> Result<()> {
+        if bits > 8 {
+            return Err(GenericError("Writing too many bits").into());
+        }
+        self.cursor.write_bits(&mut self.data, &mut self.bit_len, u64::from(value), bits)
+    }
+
+    fn write_bits(&mut self, value: u64, bits: u8) -> Result<()> {
+        self.cursor.write_bits(&mut self.data, &mut self.bit_len, value, bits)
+    }
+}
+
+// Read-only view over borrowed bytes. Cheap to create, so it can be used for
+// every frame block without copying anything.
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    cursor: BitPosition,
+    bit_len: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            cursor: BitPosition::default(),
+            bit_len: data.len() * 8,
+        }
+    }
+
+    // For data whose last byte is only partly used
+    pub fn with_len_bits(data: &'a [u8], bit_len: usize) -> BitReader<'a> {
+        BitReader {
+            data,
+            cursor: BitPosition::default(),
+            bit_len: min(bit_len, data.len() * 8),
+        }
+    }
+
+    pub fn eof(&self) -> bool {
+        self.remaining_bits() == 0
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn seek(&mut self, byte_offset: usize, bit_offset: u8) {
+        self.cursor = BitPosition::new(byte_offset, bit_offset);
+    }
+
+    pub fn position(&self) -> BitPosition {
+        self.cursor
+    }
+
+    pub fn len_bits(&self) -> usize {
+        self.bit_len
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        self.bit_len.saturating_sub(self.cursor.bits())
+    }
+
+    pub fn mark(&self) -> Mark {
+        Mark(self.cursor)
+    }
+
+    pub fn reset_to(&mut self, mark: Mark) {
+        self.cursor = mark.0;
+    }
+
+    pub fn peek_bits(&self, bits: u8) -> Result<u64> {
+        self.clone().read_bits_u64(bits)
+    }
+}
+
+impl<'a> BitRead for BitReader<'a> {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        if bits > 8 {
+            return Err(GenericError("Reading too many bits").into());
+        }
+        Ok(self.cursor.read_bits(self.data, self.bit_len, bits)? as u8)
+    }
+
+    fn read_bits(&mut self, bits: u8) -> Result<u64> {
+        self.cursor.read_bits(self.data, self.bit_len, bits)
+    }
+
+    fn eof(&mut self) -> bool {
+        BitReader::eof(self)
+    }
+}
+
+// Append-only stream that grows as it is written to.
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    data: Vec<u8>,
+    cursor: BitPosition,
+    bit_len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> BitWriter {
+        BitWriter {
+            data: Vec::with_capacity(capacity),
+            ..BitWriter::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn position(&self) -> BitPosition {
+        self.cursor
+    }
+
+    pub fn len_bits(&self) -> usize {
+        self.bit_len
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    // Throw away everything written so far but keep the allocation around
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.cursor = BitPosition::default();
+        self.bit_len = 0;
+    }
+}
+
+impl BitWrite for BitWriter {
+    fn write_bits_u8(&mut self, value: u8, bits: u8) -> Result<()> {
+        if bits > 8 {
+            return Err(GenericError("Writing too many bits").into());
+        }
+        self.cursor.write_bits(&mut self.data, &mut self.bit_len, u64::from(value), bits)
+    }
+
+    fn write_bits(&mut self, value: u64, bits: u8) -> Result<()> {
+        self.cursor.write_bits(&mut self.data, &mut self.bit_len, value, bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bytes from writing with `write_fn` into a fresh stream
+    fn written<F: FnOnce(&mut BitWriter) -> Result<()>>(write_fn: F) -> Vec<u8> {
+        let mut bs = BitWriter::new();
+        write_fn(&mut bs).unwrap();
+        bs.bytes()
+    }
+
+    #[test]
+    fn torque_ints_match_known_bits() {
+        // Sign flag first, then the magnitude in the other 7 bits
+        assert_eq!(written(|bs| bs.write_signed_int(-5, 8)), [0x0B]);
+        assert_eq!(written(|bs| bs.write_signed_int(5, 8)), [0x0A]);
+        // 3..=10 is 8 values, so 3 bits holding the offset from 3
+        assert_eq!(written(|bs| bs.write_ranged_u32(5, 3, 10)), [0x02]);
+        // writeInt with no bits writes nothing
+        assert_eq!(written(|bs| bs.write_int(0, 0)), [] as [u8; 0]);
+
+        let mut bs = BitReader::new(&[0x0B, 0x02]);
+        assert_eq!(bs.read_signed_int(8).unwrap(), -5);
+        assert_eq!(bs.read_ranged_u32(3, 10).unwrap(), 5);
+    }
+
+    #[test]
+    fn torque_floats_match_known_bits() {
+        // Torque truncates, 0.5 * 255 = 127.5 is 127
+        assert_eq!(written(|bs| bs.write_float(0.5, 8)), [0x7F]);
+        assert_eq!(written(|bs| bs.write_float(1.0, 8)), [0xFF]);
+        assert_eq!(written(|bs| bs.write_signed_float(-1.0, 8)), [0x00]);
+        assert_eq!(written(|bs| bs.write_signed_float(0.0, 8)), [0x7F]);
+        assert_eq!(written(|bs| bs.write_signed_float(1.0, 8)), [0xFF]);
+
+        let mut bs = BitReader::new(&[0xFF, 0x00, 0x7F]);
+        assert_eq!(bs.read_float(8).unwrap(), 1.0);
+        assert_eq!(bs.read_signed_float(8).unwrap(), -1.0);
+        assert_eq!(bs.read_signed_float(8).unwrap(), 127f32 * 2f32 / 255f32 - 1f32);
+    }
+
+    #[test]
+    fn torque_float_round_trips() {
+        for &value in &[0f32, -0f32, 1.5, -123.456, f32::MAX, f32::MIN_POSITIVE, f32::INFINITY] {
+            let bytes = written(|bs| bs.write_f32(value));
+            assert_eq!(BitReader::new(&bytes).read_f32().unwrap().to_bits(), value.to_bits());
+        }
+        // Every step of a 10 bit float comes back as itself
+        for step in 0..1024u32 {
+            let value = step as f32 / 1023f32;
+            let bytes = written(|bs| bs.write_float(value, 10));
+            let back = BitReader::new(&bytes).read_float(10).unwrap();
+            assert_eq!(written(|bs| bs.write_float(back, 10)), bytes);
+        }
+    }
+
+    #[test]
+    fn torque_vectors_round_trip() {
+        let normal = [0f32, 0.6, 0.8];
+        let bytes = written(|bs| bs.write_normal_vector(normal, 10));
+        let back = BitReader::new(&bytes).read_normal_vector(10).unwrap();
+        for (a, b) in normal.iter().zip(back.iter()) {
+            assert!((a - b).abs() < 0.01, "{:?} came back as {:?}", normal, back);
+        }
+
+        let origin = [100f32, -50f32, 3f32];
+        let near = [101.25f32, -50.5f32, 3f32];
+        let bytes = written(|bs| bs.write_compressed_point(near, origin, 0.01));
+        // 2 bits of kind, then 3 16 bit offsets
+        assert_eq!(bytes.len(), (2usize + 3 * 16).div_ceil(8));
+        let back = BitReader::new(&bytes).read_compressed_point(origin, 0.01).unwrap();
+        assert_eq!(back, near);
+
+        // Too far away to pack, so the floats go out as they are
+        let far = [1e9f32, 0.1, -7.25];
+        let bytes = written(|bs| bs.write_compressed_point(far, origin, 0.01));
+        assert_eq!(BitReader::new(&bytes).read_compressed_point(origin, 0.01).unwrap(), far);
+    }
+
+    #[test]
+    fn wide_writes_keep_every_bit() {
+        for &(value, bits) in &[(0xFFFF_FFFFu64, 32), (0x1_2345_6789, 40), (0xFF00_0000, 32)] {
+            let bytes = written(|bs| bs.write_bits_u64(value, bits));
+            assert_eq!(BitReader::new(&bytes).read_bits_u64(bits).unwrap(), value);
+        }
+        let bytes = written(|bs| bs.write_u64(0xDEAD_BEEF_CAFE_F00D));
+        assert_eq!(bytes, 0xDEAD_BEEF_CAFE_F00Du64.to_le_bytes());
+        assert!(BitWriter::new().write_bits_u64(1 << 40, 40).is_err());
+    }
+
+    #[test]
+    fn zero_bit_signed_and_float_codecs_are_errors() {
+        let mut bs = BitWriter::new();
+        assert!(bs.write_signed_int(0, 0).is_err());
+        assert!(bs.write_float(0.0, 0).is_err());
+        assert!(bs.write_signed_float(0.0, 0).is_err());
+        assert!(bs.write_normal_vector([0.0, 0.0, 1.0], 0).is_err());
+        assert!(bs.write_float(0.0, 33).is_err());
+
+        let mut bs = BitReader::new(&[0xFF; 8]);
+        assert!(bs.read_signed_int(0).is_err());
+        assert!(bs.read_float(0).is_err());
+        assert!(bs.read_signed_float(0).is_err());
+        assert!(bs.read_normal_vector(0).is_err());
+        assert!(bs.read_normal_vector(255).is_err());
+    }
+
+    #[test]
+    fn peek_does_not_move_the_cursor() {
+        let mut bs = BitReader::new(&[0b1010_1101, 0xFF]);
+        bs.read_bits_u8(3).unwrap();
+        assert_eq!(bs.position(), BitPosition::new(0, 3));
+        // Straddles the byte boundary
+        assert_eq!(bs.peek_bits(7).unwrap(), 0b111_0101);
+        assert_eq!(bs.peek_bits(7).unwrap(), 0b111_0101);
+        assert_eq!(bs.position(), BitPosition::new(0, 3));
+        assert_eq!(bs.read_bits_u8(7).unwrap(), 0b111_0101);
+        assert_eq!(bs.position(), BitPosition::new(1, 2));
+        // Peeking past the end fails and leaves the cursor alone
+        assert!(bs.peek_bits(7).is_err());
+        assert_eq!(bs.position().bits(), 10);
+
+        let mut bs = BitStream::new(vec![0b1010_1101]);
+        bs.read_bits_u8(1).unwrap();
+        assert_eq!(bs.peek_bits(4).unwrap(), 0b0110);
+        assert_eq!(bs.position(), BitPosition::new(0, 1));
+    }
+
+    #[test]
+    fn reset_goes_back_to_the_mark() {
+        let data = [0x12, 0x34, 0x56];
+        let mut bs = BitReader::new(&data);
+        bs.read_bits_u8(5).unwrap();
+        let mark = bs.mark();
+        let first = bs.read_bits_u16(12).unwrap();
+        assert_eq!(bs.position().bits(), 17);
+        bs.reset_to(mark);
+        assert_eq!(bs.position(), BitPosition::new(0, 5));
+        assert_eq!(bs.read_bits_u16(12).unwrap(), first);
+
+        // A reset after running into the end can read again
+        let mark = bs.mark();
+        assert!(bs.read_bits_u16(16).is_err());
+        bs.reset_to(mark);
+        assert_eq!(bs.read_bits_u8(7).unwrap(), 0x56 >> 1);
+        assert!(bs.eof());
+
+        let mut bs = BitStream::new(data.to_vec());
+        let mark = bs.mark();
+        assert_eq!(bs.read_u16().unwrap(), 0x3412);
+        bs.reset_to(mark);
+        assert_eq!(bs.position(), BitPosition::default());
+        assert_eq!(bs.read_u8().unwrap(), 0x12);
+    }
+
+    #[test]
+    fn eof_at_the_last_bit() {
+        // Exactly at the end of the last byte
+        let mut bs = BitReader::new(&[0xAB, 0xCD]);
+        bs.read_bits_u8(8).unwrap();
+        bs.read_bits_u8(7).unwrap();
+        assert!(!bs.eof());
+        assert_eq!(bs.remaining_bits(), 1);
+        bs.read_bits_u8(1).unwrap();
+        assert!(bs.eof());
+        assert!(bs.read_bits_u8(1).unwrap_err().is_eof());
+
+        // A length that ends part way through a byte
+        let mut bs = BitReader::with_len_bits(&[0xFF, 0xFF], 11);
+        assert_eq!(bs.read_bits_u16(10).unwrap(), 0x3FF);
+        assert!(!bs.eof());
+        assert!(bs.read_bits_u8(2).is_err());
+        assert_eq!(bs.read_bits_u8(1).unwrap(), 1);
+        assert!(bs.eof());
+        assert_eq!(bs.position(), BitPosition::new(1, 3));
+
+        // Written streams end after the last written bit, not the last byte
+        let mut bs = BitStream::new(vec![]);
+        bs.write_bits_u8(0b101, 3).unwrap();
+        assert_eq!(bs.len_bits(), 3);
+        bs.seek(0, 0);
+        assert_eq!(bs.read_bits_u8(3).unwrap(), 0b101);
+        assert!(bs.eof());
+    }
+
+    mod legacy_api {
+        // Only BitStream, the way callers from before BitRead/BitWrite import it
+        use crate::bit_stream::BitStream;
+
+        #[test]
+        fn inherent_api_without_traits() {
+            let mut bs = BitStream::new(vec![]);
+            bs.write_bits_u8(5, 3).unwrap();
+            bs.write_u16(0xBEEF).unwrap();
+            bs.write_bits_u64(0x1_0045_6789, 40).unwrap();
+            bs.write_optional(Some(7u8), |bs, v| bs.write_u8(v)).unwrap();
+            bs.write_scaled_f64_bits(0.5, 6, 1f64 / 16f64, -1f64).unwrap();
+            bs.write_string("movement.mis".into()).unwrap();
+
+            let mut bs = BitStream::new(bs.bytes());
+            assert_eq!(bs.read_bits_u8(3).unwrap(), 5);
+            assert_eq!(bs.read_u16().unwrap(), 0xBEEF);
+            assert_eq!(bs.read_bits_u64(40).unwrap(), 0x1_0045_6789);
+            assert_eq!(bs.read_optional(|bs| bs.read_u8()).unwrap(), Some(7));
+            assert_eq!(bs.read_scaled_f64_bits(6, 1f64 / 16f64, -1f64).unwrap(), 0.5);
+            assert_eq!(bs.read_string().unwrap(), "movement.mis");
+        }
     }
 }
@@ -0,0 +1,475 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+use core::fmt::Write;
+use serde::Serialize;
+use crate::error::Result;
+use crate::error::ErrorKind::GenericError2;
+use crate::math;
+use crate::quantize::Quantization;
+use crate::recording::{Angle, Frame, Move, MoveSlot, Recording};
+#[cfg(feature = "tas")]
+use crate::tas_rec::Sequence;
+use crate::timeline::Timeline;
+
+// How far the game lets the camera look down (positive) and up (negative), in
+// radians. Turning past these is thrown away, so looking back the other way
+// starts moving again straight off. The defaults are the clamp the marble puts
+// on its own pitch as it adds up moves, Marble::processTick in the engine's
+// marble.cc (see OpenMBU's engine/source/game/marble/marble.cpp for the same
+// code in the open).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CameraLimits {
+    pub min_pitch: f64,
+    pub max_pitch: f64,
+}
+
+impl CameraLimits {
+    pub const MIN_PITCH: f64 = -0.35 * PI;
+    pub const MAX_PITCH: f64 = 0.45 * PI;
+
+    pub fn clamp(&self, pitch: f64) -> f64 {
+        pitch.max(self.min_pitch).min(self.max_pitch)
+    }
+}
+
+impl Default for CameraLimits {
+    fn default() -> CameraLimits {
+        CameraLimits {
+            min_pitch: CameraLimits::MIN_PITCH,
+            max_pitch: CameraLimits::MAX_PITCH,
+        }
+    }
+}
+
+// Where the camera is pointing, in radians. Yaw and roll keep counting past a
+// full turn so they don't jump around when plotted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Orientation {
+    pub yaw: f64,
+    pub pitch: f64,
+    pub roll: f64,
+}
+
+impl Orientation {
+    // Yaw wrapped into [-pi, pi), which way the camera is facing
+    pub fn heading(&self) -> f64 {
+        wrap(self.yaw)
+    }
+}
+
+// Where the camera points after one move
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CameraSample {
+    pub frame: usize,
+    pub slot: usize,
+    // When the frame the move is in ends, in ms
+    pub time: u64,
+    pub orientation: Orientation,
+    // The move tried to look further up or down than the game lets it
+    pub clamped: bool,
+}
+
+// The camera's orientation over a rec, made by adding up every move's yaw,
+// pitch and roll. Moves only say how much to turn since the last tick, so
+// this needs to start from where the camera was, which a rec doesn't know
+// (it's up to the mission's start pad). Recording::camera_path starts from
+// facing straight ahead at 0.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CameraPath {
+    pub start: Orientation,
+    pub limits: CameraLimits,
+    pub samples: Vec<CameraSample>,
+}
+
+impl CameraPath {
+    pub fn new<'a, I>(frames: I, start: Orientation, limits: CameraLimits) -> CameraPath
+    where
+        I: IntoIterator<Item = &'a Frame>,
+        I::IntoIter: Clone,
+    {
+        let frames = frames.into_iter();
+        let timeline = Timeline::new(frames.clone());
+        let mut orientation = Orientation {
+            pitch: limits.clamp(start.pitch),
+            ..start
+        };
+        let mut samples = Vec::new();
+        for (i, frame) in frames.enumerate() {
+            for &slot in MoveSlot::ALL.iter() {
+                let mv = match frame.get_move(slot) {
+                    Some(mv) => mv,
+                    None => continue,
+                };
+                let pitch = orientation.pitch + mv.pitch().unwrap_or(0f64);
+                orientation.yaw += mv.yaw().unwrap_or(0f64);
+                orientation.roll += mv.roll().unwrap_or(0f64);
+                orientation.pitch = limits.clamp(pitch);
+                samples.push(CameraSample {
+                    frame: i,
+                    slot: slot.index(),
+                    time: timeline.end_of(i).unwrap_or(0),
+                    orientation,
+                    clamped: orientation.pitch != pitch,
+                });
+            }
+        }
+        CameraPath {
+            start,
+            limits,
+            samples,
+        }
+    }
+
+    // Where the camera points once every move has run
+    pub fn end(&self) -> Orientation {
+        self.samples.last().map_or(self.start, |sample| sample.orientation)
+    }
+
+    // Where the camera points at `ms`, which is where the last move before
+    // it left it
+    pub fn at(&self, ms: u64) -> Orientation {
+        let count = self.samples.partition_point(|sample| sample.time <= ms);
+        match count {
+            0 => self.start,
+            _ => self.samples[count - 1].orientation,
+        }
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // A row per move, for spreadsheets and plotting
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("frame,slot,time,yaw,pitch,roll,heading,clamped\n");
+        for sample in &self.samples {
+            let orientation = &sample.orientation;
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{},{},{}",
+                sample.frame,
+                sample.slot,
+                sample.time,
+                orientation.yaw,
+                orientation.pitch,
+                orientation.roll,
+                orientation.heading(),
+                sample.clamped
+            );
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SteerOptions {
+    // Where the camera points before the first frame
+    pub start: Orientation,
+    pub limits: CameraLimits,
+    // Nearest keeps the camera within half a step of every target, Truncate
+    // and Floor within a whole one
+    pub quantization: Quantization,
+}
+
+// Set the yaw, pitch and roll of every move in `frames` so the camera ends
+// each frame pointing at that frame's target, going the shortest way round.
+// Each move turns from where the moves so far actually left the camera after
+// being written, rather than from the last target, so rounding can't add up
+// into drift. Frames without moves can't turn, so the frame after them makes
+// up for it. Returns the path the frames now take.
+pub fn steer(frames: &mut [Frame], targets: &[Orientation], options: &SteerOptions) -> Result<CameraPath> {
+    if frames.len() != targets.len() {
+        return Err(GenericError2(format!("{} camera targets for {} frames", targets.len(), frames.len())).into());
+    }
+    let limits = options.limits;
+    let mut camera = Orientation {
+        pitch: limits.clamp(options.start.pitch),
+        ..options.start
+    };
+    for (frame, target) in frames.iter_mut().zip(targets.iter()) {
+        for mv in frame.moves.iter_mut().flatten() {
+            let yaw = turn(target.yaw - camera.yaw, options.quantization)?;
+            let pitch = turn(limits.clamp(target.pitch) - camera.pitch, options.quantization)?;
+            let roll = turn(target.roll - camera.roll, options.quantization)?;
+            camera.yaw += yaw.to_f64();
+            camera.pitch = limits.clamp(camera.pitch + pitch.to_f64());
+            camera.roll += roll.to_f64();
+            set_turn(mv, yaw, pitch, roll);
+        }
+    }
+    Ok(CameraPath::new(&*frames, options.start, limits))
+}
+
+impl Recording {
+    pub fn camera_path(&self) -> CameraPath {
+        CameraPath::new(&self.frames, Orientation::default(), CameraLimits::default())
+    }
+
+    // See camera::steer, there has to be a target for every frame
+    pub fn steer_camera(&mut self, targets: &[Orientation], options: &SteerOptions) -> Result<CameraPath> {
+        steer(&mut self.frames, targets, options)
+    }
+}
+
+#[cfg(feature = "tas")]
+impl Sequence {
+    // Sequences after the first should start where the one before ended,
+    // which is the `end()` of the path this returns
+    pub fn steer_camera(&mut self, targets: &[Orientation], options: &SteerOptions) -> Result<CameraPath> {
+        steer(&mut self.frames, targets, options)
+    }
+}
+
+// `angle` wrapped the shortest way round and snapped to a step the rec can
+// hold
+fn turn(angle: f64, policy: Quantization) -> Result<Angle> {
+    Angle::from_f64(wrap(angle), policy).map(|(angle, _)| angle)
+}
+
+// The game leaves out angles that don't turn
+fn set_turn(mv: &mut Move, yaw: Angle, pitch: Angle, roll: Angle) {
+    let angle = |turn: Angle| if turn == Angle(0) { None } else { Some(turn) };
+    mv.yaw = angle(yaw);
+    mv.pitch = angle(pitch);
+    mv.roll = angle(roll);
+}
+
+fn wrap(angle: f64) -> f64 {
+    angle - 2f64 * PI * math::floor((angle + PI) / (2f64 * PI))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use crate::encoding::Encoding;
+    use crate::quantize::{OutOfRange, Rounding};
+    use crate::recording::{Axis, Triggers};
+
+    // One Angle step
+    const STEP: f64 = 2f64 * PI / 65536f64;
+
+    fn still() -> Move {
+        Move {
+            yaw: None,
+            pitch: None,
+            roll: None,
+            mx: Axis(16),
+            my: Axis(16),
+            mz: Axis(16),
+            freelook: true,
+            triggers: Triggers::empty(),
+        }
+    }
+
+    fn rec(frames: Vec<Frame>) -> Recording {
+        Recording {
+            mission: "marble/data/missions/beginner/movement.mis".to_string(),
+            frames,
+            mission_encoding: Encoding::Utf8,
+            framing: None,
+            metadata: None,
+        }
+    }
+
+    fn target(yaw: f64, pitch: f64, roll: f64) -> Orientation {
+        Orientation { yaw, pitch, roll }
+    }
+
+    // Across pi and back, all the way round, and up and down past the limits
+    fn targets() -> Vec<Orientation> {
+        vec![
+            target(0.1, 0.2, 0f64),
+            target(1.3, 0.5, 0.01),
+            target(3.1, -0.4, 0f64),
+            target(-3.1, 0.1, -0.02),
+            target(-2f64, 2f64, 0f64),
+            target(2.9, 1.4, 0f64),
+            target(-3.05, -2f64, 0.3),
+            target(-1f64, -1f64, 0f64),
+            target(0.5, 0.7, 0f64),
+            target(0.5 + STEP * 0.6, 0.7 - STEP * 0.3, 0f64),
+            target(7f64, 0.3, 0f64),
+            target(-7f64, 0f64, 0f64),
+        ]
+    }
+
+    // Last sample of every frame, where the frame leaves the camera
+    fn frame_ends(path: &CameraPath, frames: usize) -> Vec<Option<Orientation>> {
+        let mut ends = vec![None; frames];
+        for sample in &path.samples {
+            ends[sample.frame] = Some(sample.orientation);
+        }
+        ends
+    }
+
+    fn assert_on_target(ends: &[Option<Orientation>], targets: &[Orientation], limits: CameraLimits) {
+        for (i, (end, target)) in ends.iter().zip(targets.iter()).enumerate() {
+            let end = match end {
+                Some(end) => end,
+                None => continue,
+            };
+            let yaw = wrap(end.yaw - target.yaw).abs();
+            let pitch = (end.pitch - limits.clamp(target.pitch)).abs();
+            let roll = wrap(end.roll - target.roll).abs();
+            assert!(yaw <= STEP, "frame {} yaw {} off", i, yaw);
+            assert!(pitch <= STEP, "frame {} pitch {} off", i, pitch);
+            assert!(roll <= STEP, "frame {} roll {} off", i, roll);
+        }
+    }
+
+    fn turning(yaw: i32, pitch: i32) -> Frame {
+        let angle = |steps: i32| if steps == 0 { None } else { Some(Angle(steps as u16)) };
+        Frame {
+            moves: [Some(Move { yaw: angle(yaw), pitch: angle(pitch), ..still() }), None],
+            delta: 16,
+        }
+    }
+
+    #[test]
+    fn yaw_keeps_counting_past_a_turn() {
+        // A quarter turn every frame, five times round
+        let frames = vec![turning(16384, 0); 20];
+        let path = rec(frames).camera_path();
+        assert_eq!(path.samples.len(), 20);
+        for (i, sample) in path.samples.iter().enumerate() {
+            assert_eq!(sample.frame, i);
+            assert_eq!(sample.time, 16 * (i as u64 + 1));
+            assert!((sample.orientation.yaw - (i + 1) as f64 * PI / 2f64).abs() < 1e-9);
+            assert!((-PI..PI).contains(&sample.orientation.heading()));
+        }
+        assert!((path.end().yaw - 10f64 * PI).abs() < 1e-9);
+
+        // Turning back the other way comes back down past 0
+        let back = rec(vec![turning(-16384, 0); 6]).camera_path();
+        assert!((back.end().yaw + 3f64 * PI).abs() < 1e-9);
+        assert!((back.end().heading() + PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pitch_stops_at_both_limits() {
+        let limits = CameraLimits::default();
+        // An eighth of a turn down at a time, then back up
+        let mut frames = vec![turning(0, 8192); 4];
+        frames.extend(vec![turning(0, -8192); 8]);
+        let path = rec(frames).camera_path();
+        let pitches: Vec<f64> = path.samples.iter().map(|sample| sample.orientation.pitch).collect();
+        let clamped: Vec<bool> = path.samples.iter().map(|sample| sample.clamped).collect();
+
+        assert!((pitches[0] - PI / 4f64).abs() < 1e-9);
+        assert_eq!(pitches[1], CameraLimits::MAX_PITCH);
+        assert_eq!(pitches[3], CameraLimits::MAX_PITCH);
+        assert_eq!(&clamped[..4], [false, true, true, true]);
+        // Straight back down off the limit, nothing was saved up past it
+        assert!((pitches[4] - (CameraLimits::MAX_PITCH - PI / 4f64)).abs() < 1e-9);
+        assert!(!clamped[4]);
+        assert_eq!(pitches[11], CameraLimits::MIN_PITCH);
+        assert!(clamped[11]);
+        assert!(pitches.iter().all(|&pitch| limits.clamp(pitch) == pitch));
+
+        // A start past the limit is pulled back in
+        let start = Orientation { pitch: 3f64, ..Orientation::default() };
+        let path = CameraPath::new(&[turning(0, 0)], start, limits);
+        assert_eq!(path.end().pitch, CameraLimits::MAX_PITCH);
+        assert_eq!(path.at(0), start);
+    }
+
+    #[test]
+    fn at_is_the_last_move_before() {
+        let path = rec(vec![turning(16384, 0), Frame { moves: [None, None], delta: 16 }, turning(16384, 0)]).camera_path();
+        assert_eq!(path.at(15), Orientation::default());
+        assert_eq!(path.at(16), path.samples[0].orientation);
+        assert_eq!(path.at(47), path.samples[0].orientation);
+        assert_eq!(path.at(48), path.end());
+        assert_eq!(path.at(1000), path.end());
+    }
+
+    #[test]
+    fn csv_has_a_row_per_move() {
+        let mut frames = vec![turning(16384, 8192)];
+        frames[0].moves[1] = Some(Move { pitch: Some(Angle(16384)), ..still() });
+        let csv = rec(frames).camera_path().to_csv();
+        let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows[0], ["frame", "slot", "time", "yaw", "pitch", "roll", "heading", "clamped"]);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1][..3], ["0", "0", "16"]);
+        assert_eq!(rows[2][..3], ["0", "1", "16"]);
+        assert_eq!(rows[1][3].parse::<f64>().unwrap(), PI / 2f64);
+        assert_eq!(rows[2][4].parse::<f64>().unwrap(), CameraLimits::MAX_PITCH);
+        assert_eq!(rows[1][7], "false");
+        assert_eq!(rows[2][7], "true");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_has_the_samples_and_limits() {
+        let path = rec(vec![turning(16384, 0); 2]).camera_path();
+        let json: serde_json::Value = serde_json::from_str(&path.to_json().unwrap()).unwrap();
+        assert_eq!(json["limits"]["max_pitch"], CameraLimits::MAX_PITCH);
+        assert_eq!(json["start"]["yaw"], 0f64);
+        let samples = json["samples"].as_array().unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1]["time"], 32);
+        assert_eq!(samples[1]["orientation"]["yaw"], PI);
+        assert_eq!(samples[1]["clamped"], false);
+    }
+
+    #[test]
+    fn steered_rec_reads_back_on_target() {
+        let roundings = [Rounding::Truncate, Rounding::Nearest, Rounding::Floor];
+        for &rounding in roundings.iter() {
+            let targets = targets();
+            let frames = (0..targets.len())
+                .map(|i| Frame {
+                    // Some frames get two moves, the second has nothing left
+                    // to turn
+                    moves: [Some(still()), if i % 3 == 0 { Some(still()) } else { None }],
+                    delta: 16,
+                })
+                .collect();
+            let mut rec = rec(frames);
+            let options = SteerOptions {
+                quantization: Quantization::new(rounding, OutOfRange::Error),
+                ..SteerOptions::default()
+            };
+            let steered = rec.steer_camera(&targets, &options).unwrap();
+
+            let back = Recording::from_bytes(&rec.into_bytes().unwrap()).unwrap();
+            let path = back.camera_path();
+            assert_eq!(path, steered, "{:?}", rounding);
+            assert_on_target(&frame_ends(&path, targets.len()), &targets, options.limits);
+            // The second move of a frame never turns
+            for frame in back.frames.iter().step_by(3) {
+                let second = frame.second_move().unwrap();
+                assert_eq!((second.yaw, second.pitch, second.roll), (None, None, None));
+            }
+        }
+    }
+
+    #[test]
+    fn frames_without_moves_are_made_up_for() {
+        let targets = targets();
+        let frames = (0..targets.len())
+            .map(|i| Frame {
+                moves: [if i % 4 == 1 { None } else { Some(still()) }, None],
+                delta: 16,
+            })
+            .collect();
+        let mut rec = rec(frames);
+        rec.steer_camera(&targets, &SteerOptions::default()).unwrap();
+        let back = Recording::from_bytes(&rec.into_bytes().unwrap()).unwrap();
+        let ends = frame_ends(&back.camera_path(), targets.len());
+        assert!(ends[1].is_none());
+        assert_on_target(&ends, &targets, CameraLimits::default());
+    }
+
+    #[test]
+    fn steering_needs_a_target_per_frame() {
+        let mut rec = rec(vec![Frame { moves: [Some(still()), None], delta: 16 }]);
+        assert!(rec.steer_camera(&targets(), &SteerOptions::default()).is_err());
+    }
+}
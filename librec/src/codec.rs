@@ -0,0 +1,157 @@
+use crate::bit_stream::{BitRead, BitWrite};
+use crate::error::Result;
+use crate::quantize::Quantization;
+
+pub use librec_derive::BitCodec;
+
+// Something with a fixed bit layout. Usually derived, see librec_derive for
+// the field attributes, so the read and write sides can't drift apart.
+pub trait BitCodec: Sized {
+    fn read_from<R: BitRead>(bs: &mut R) -> Result<Self>;
+
+    fn write_to<W: BitWrite>(&self, bs: &mut W, policy: Quantization) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use crate::bit_stream::{BitReader, BitWriter};
+    use crate::dissect::Dissector;
+    use crate::quantize::{OutOfRange, Rounding};
+
+    // Every attribute path the derive has
+    #[derive(Debug, Clone, PartialEq, BitCodec)]
+    struct Everything {
+        flag: bool,
+        byte: u8,
+        #[bits(10)]
+        short: u16,
+        #[bits(6, scale = 1/16, offset = -1)]
+        axis: f64,
+        #[bits(8, scale = 0.5)]
+        half: f64,
+        #[optional]
+        #[bits(12)]
+        maybe: Option<u16>,
+        #[optional]
+        nothing: Option<u32>,
+        held: [bool; 3],
+        #[optional]
+        #[bits(4)]
+        #[codec(element = "slot")]
+        slots: [Option<u8>; 2],
+        #[codec(with = "nibble")]
+        custom: u8,
+        name: String,
+        inner: Inner,
+    }
+
+    #[derive(Debug, Clone, PartialEq, BitCodec)]
+    struct Inner {
+        #[bits(3)]
+        value: u8,
+    }
+
+    mod nibble {
+        use super::*;
+
+        pub fn read<R: BitRead>(bs: &mut R) -> Result<u8> {
+            bs.read_bits_u8(4)
+        }
+
+        pub fn write<W: BitWrite>(bs: &mut W, value: &u8, _policy: Quantization) -> Result<f64> {
+            bs.write_bits_u8(*value, 4).map(|_| 0f64)
+        }
+    }
+
+    fn everything() -> Everything {
+        Everything {
+            flag: true,
+            byte: 0xA5,
+            short: 1000,
+            axis: 0.1,
+            half: 3.7,
+            maybe: Some(0xABC),
+            nothing: None,
+            held: [true, false, true],
+            slots: [None, Some(9)],
+            custom: 0xE,
+            name: "mbg".to_string(),
+            inner: Inner { value: 5 },
+        }
+    }
+
+    // The same thing written out field by field
+    fn by_hand(value: &Everything, axis: u8, half: u8) -> Vec<u8> {
+        let mut bs = BitWriter::new();
+        bs.write_bool(value.flag).unwrap();
+        bs.write_u8(value.byte).unwrap();
+        bs.write_bits_u16(value.short, 10).unwrap();
+        bs.write_bits_u8(axis, 6).unwrap();
+        bs.write_bits_u8(half, 8).unwrap();
+        bs.write_bool(true).unwrap();
+        bs.write_bits_u16(value.maybe.unwrap(), 12).unwrap();
+        bs.write_bool(false).unwrap();
+        for &held in &value.held {
+            bs.write_bool(held).unwrap();
+        }
+        bs.write_bool(false).unwrap();
+        bs.write_bool(true).unwrap();
+        bs.write_bits_u8(9, 4).unwrap();
+        bs.write_bits_u8(value.custom, 4).unwrap();
+        bs.write_string(value.name.clone()).unwrap();
+        bs.write_bits_u8(value.inner.value, 3).unwrap();
+        bs.bytes()
+    }
+
+    fn write(value: &Everything, policy: Quantization) -> Result<Vec<u8>> {
+        let mut bs = BitWriter::new();
+        value.write_to(&mut bs, policy)?;
+        Ok(bs.bytes())
+    }
+
+    #[test]
+    fn derived_layout_matches_the_hand_written_one() {
+        let value = everything();
+        // 0.1 is 17.6 steps up from -1, 3.7 is 7.4 halves
+        assert_eq!(write(&value, Quantization::default()).unwrap(), by_hand(&value, 17, 7));
+        let nearest = Quantization::new(Rounding::Nearest, OutOfRange::Error);
+        assert_eq!(write(&value, nearest).unwrap(), by_hand(&value, 18, 7));
+
+        let bytes = by_hand(&value, 17, 7);
+        let back = Everything::read_from(&mut BitReader::new(&bytes)).unwrap();
+        assert_eq!(back, Everything { axis: 0.0625, half: 3.5, ..value });
+    }
+
+    #[test]
+    fn derived_scaled_fields_follow_the_policy() {
+        let value = Everything {
+            axis: 3.5,
+            ..everything()
+        };
+        assert!(write(&value, Quantization::default()).is_err());
+        let saturate = Quantization::new(Rounding::Truncate, OutOfRange::Saturate);
+        assert_eq!(write(&value, saturate).unwrap(), by_hand(&value, 63, 7));
+    }
+
+    #[test]
+    fn derived_fields_are_named() {
+        let mut bs = BitWriter::new();
+        everything().write_to(&mut bs, Quantization::default()).unwrap();
+        let bytes = bs.bytes();
+        let mut dissector = Dissector::new(BitReader::new(&bytes));
+        Everything::read_from(&mut dissector).unwrap();
+        let fields = dissector.into_fields();
+        let names: Vec<&str> = fields.iter().map(|field| field.name).collect();
+        assert_eq!(
+            names,
+            ["flag", "byte", "short", "axis", "half", "maybe", "nothing", "held", "slots", "custom", "name", "inner"]
+        );
+        // Offsets follow the hand-written layout: 1 + 8 + 10 bits in
+        assert_eq!((fields[3].start, fields[3].end), (19, 25));
+        assert_eq!(fields[7].children.len(), 3);
+        assert_eq!(fields[11].children[0].name, "value");
+    }
+}
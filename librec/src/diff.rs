@@ -0,0 +1,381 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use serde::Serialize;
+use crate::field::{FieldValue, ToFieldValue};
+#[cfg(feature = "json")]
+use crate::error::Result;
+use crate::recording::{Frame, Move, Recording, Trigger};
+use crate::timeline::Timeline;
+
+// One field that is different in the second rec
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    // Path to the field in the frame: `delta`, `moves[1].yaw`,
+    // `moves[0].triggers.jump`. A move that only one of the recs has is just
+    // `moves[0]`.
+    pub field: String,
+    pub a: FieldValue,
+    pub b: FieldValue,
+}
+
+// A frame that is different in the second rec. Frames are paired up in order
+// for as long as they start at the same time, and after that with the frame
+// of the second rec running when the first rec's starts, so one frame added
+// or taken out shows up as just that instead of every frame after it being
+// different. A frame only one of the recs has has no index in the other.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrameDiff {
+    pub index_a: Option<usize>,
+    pub index_b: Option<usize>,
+    // When the frame starts in each rec, in ms. For a frame only one rec has,
+    // where the other one is at.
+    pub time_a: u64,
+    pub time_b: u64,
+    // How far behind the second rec is by the end of this frame, in ms
+    pub drift: i64,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diff {
+    pub mission_a: String,
+    pub mission_b: String,
+    pub frames_a: usize,
+    pub frames_b: usize,
+    pub duration_a: u64,
+    pub duration_b: u64,
+    // How far behind the second rec finishes, in ms
+    pub drift: i64,
+    // First frame of `a` that is different, or the end of `a` if `b` just
+    // keeps going
+    pub first_divergence: Option<usize>,
+    // Every frame that is different or only in one of the recs, up to where
+    // one of them ends
+    pub frames: Vec<FrameDiff>,
+    // Frames of `a` that start after `b` is over, and frames of `b` after the
+    // last one lined up with a frame of `a`
+    pub past_end_a: usize,
+    pub past_end_b: usize,
+}
+
+// Compare `b` against `a` frame by frame, the way a desynced re-export of a
+// rec would need to be looked at
+pub fn diff(a: &Recording, b: &Recording) -> Diff {
+    let timeline_a = a.timeline();
+    let timeline_b = b.timeline();
+    let mut frames = vec![];
+    let mut first_divergence = None;
+    let mut past_end_a = 0;
+    // First frame of b not lined up with anything yet
+    let mut next_b = 0;
+    for (index_a, frame_a) in a.frames.iter().enumerate() {
+        let time_a = timeline_a.time_of(index_a).unwrap_or(0);
+        let end_a = timeline_a.end_of(index_a).unwrap_or(time_a);
+        let index_b = match line_up(&b.frames, &timeline_b, next_b, frame_a, time_a) {
+            Some(index_b) => index_b,
+            None => {
+                past_end_a = a.frames.len() - index_a;
+                first_divergence.get_or_insert(index_a);
+                break;
+            }
+        };
+
+        // The frame of b running now was already lined up with an earlier
+        // frame of a, so b doesn't have this one
+        if index_b < next_b {
+            let time_b = timeline_b.time_of(next_b).unwrap_or(0);
+            first_divergence.get_or_insert(index_a);
+            frames.push(FrameDiff {
+                index_a: Some(index_a),
+                index_b: None,
+                time_a,
+                time_b,
+                drift: time_b as i64 - end_a as i64,
+                changes: vec![],
+            });
+            continue;
+        }
+
+        // And frames of b that got skipped over aren't in a
+        for skipped in next_b..index_b {
+            let time_b = timeline_b.time_of(skipped).unwrap_or(0);
+            first_divergence.get_or_insert(index_a);
+            frames.push(FrameDiff {
+                index_a: None,
+                index_b: Some(skipped),
+                time_a,
+                time_b,
+                drift: timeline_b.end_of(skipped).unwrap_or(time_b) as i64 - time_a as i64,
+                changes: vec![],
+            });
+        }
+        next_b = index_b + 1;
+
+        let mut changes = vec![];
+        diff_frames(&mut changes, frame_a, &b.frames[index_b]);
+        if changes.is_empty() {
+            continue;
+        }
+        let time_b = timeline_b.time_of(index_b).unwrap_or(0);
+        first_divergence.get_or_insert(index_a);
+        frames.push(FrameDiff {
+            index_a: Some(index_a),
+            index_b: Some(index_b),
+            time_a,
+            time_b,
+            drift: timeline_b.end_of(index_b).unwrap_or(time_b) as i64 - end_a as i64,
+            changes,
+        });
+    }
+
+    let past_end_b = b.frames.len() - next_b;
+    if past_end_b > 0 {
+        first_divergence.get_or_insert(a.frames.len());
+    }
+    Diff {
+        mission_a: a.mission.clone(),
+        mission_b: b.mission.clone(),
+        frames_a: a.frames.len(),
+        frames_b: b.frames.len(),
+        duration_a: timeline_a.duration(),
+        duration_b: timeline_b.duration(),
+        drift: timeline_b.duration() as i64 - timeline_a.duration() as i64,
+        first_divergence,
+        frames,
+        past_end_a,
+        past_end_b,
+    }
+}
+
+// Frame of b to compare a frame of a starting at `time_a` against. Out of the
+// frames of b starting then, one that is the same wins, so a 0 ms frame added
+// in front doesn't get compared instead. With none starting then it's the one
+// running at the time, None once b is over.
+fn line_up(frames_b: &[Frame], timeline_b: &Timeline, next_b: usize, frame_a: &Frame, time_a: u64) -> Option<usize> {
+    let mut starting = (next_b..frames_b.len()).take_while(|&index| timeline_b.time_of(index) == Some(time_a));
+    let first = starting.clone().next();
+    let same = starting.find(|&index| {
+        let mut changes = vec![];
+        diff_frames(&mut changes, frame_a, &frames_b[index]);
+        changes.is_empty()
+    });
+    same.or(first).or_else(|| timeline_b.frame_at(time_a))
+}
+
+impl Diff {
+    pub fn is_same(&self) -> bool {
+        self.mission_a == self.mission_b && self.first_divergence.is_none()
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // Summary and then a paragraph per different frame
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        if self.mission_a != self.mission_b {
+            let _ = writeln!(out, "mission: {:?} -> {:?}", self.mission_a, self.mission_b);
+        }
+        let _ = writeln!(out, "frames: {} -> {}", self.frames_a, self.frames_b);
+        let _ = writeln!(
+            out,
+            "duration: {} -> {} ms (drift {:+} ms)",
+            self.duration_a, self.duration_b, self.drift
+        );
+        match self.first_divergence {
+            Some(index) => {
+                let _ = writeln!(out, "first divergence: frame {}", index);
+            }
+            None => {
+                let _ = writeln!(out, "frames are the same");
+            }
+        }
+
+        for frame in &self.frames {
+            let _ = match (frame.index_a, frame.index_b) {
+                (Some(index_a), Some(index_b)) => writeln!(
+                    out,
+                    "\nframe {} at {} ms -> frame {} at {} ms (drift {:+} ms)",
+                    index_a, frame.time_a, index_b, frame.time_b, frame.drift
+                ),
+                (Some(index_a), None) => writeln!(
+                    out,
+                    "\nframe {} at {} ms is not in b (drift {:+} ms)",
+                    index_a, frame.time_a, frame.drift
+                ),
+                (None, Some(index_b)) => writeln!(
+                    out,
+                    "\nframe {} of b at {} ms is not in a (drift {:+} ms)",
+                    index_b, frame.time_b, frame.drift
+                ),
+                (None, None) => Ok(()),
+            };
+            for change in &frame.changes {
+                let _ = writeln!(out, "  {}: {} -> {}", change.field, change.a, change.b);
+            }
+        }
+        if self.past_end_a > 0 {
+            let _ = writeln!(out, "\na has {} more frames after b is over", self.past_end_a);
+        }
+        if self.past_end_b > 0 {
+            let _ = writeln!(out, "\nb has {} more frames after a is over", self.past_end_b);
+        }
+        out
+    }
+}
+
+fn diff_frames(changes: &mut Vec<Change>, a: &Frame, b: &Frame) {
+    push_change(changes, || "delta".to_string(), &a.delta, &b.delta, a.delta == b.delta);
+    for (slot, (a, b)) in a.moves.iter().zip(b.moves.iter()).enumerate() {
+        match (a, b) {
+            (Some(a), Some(b)) => diff_moves(changes, slot, a, b),
+            (None, None) => {}
+            _ => push_change(changes, || format!("moves[{}]", slot), &a.is_some(), &b.is_some(), false),
+        }
+    }
+}
+
+fn diff_moves(changes: &mut Vec<Change>, slot: usize, a: &Move, b: &Move) {
+    let angles = [("yaw", a.yaw, b.yaw), ("pitch", a.pitch, b.pitch), ("roll", a.roll, b.roll)];
+    for (name, a, b) in angles.iter() {
+        push_change(changes, || format!("moves[{}].{}", slot, name), a, b, a == b);
+    }
+    let axes = [("mx", a.mx, b.mx), ("my", a.my, b.my), ("mz", a.mz, b.mz)];
+    for (name, a, b) in axes.iter() {
+        push_change(changes, || format!("moves[{}].{}", slot, name), a, b, a == b);
+    }
+    push_change(changes, || format!("moves[{}].freelook", slot), &a.freelook, &b.freelook, a.freelook == b.freelook);
+    for &trigger in Trigger::ALL.iter() {
+        let (a, b) = (a.triggers.pressed(trigger), b.triggers.pressed(trigger));
+        push_change(changes, || format!("moves[{}].triggers.{}", slot, trigger), &a, &b, a == b);
+    }
+}
+
+// `field` is only built for fields that changed, most of them don't
+fn push_change<T: ToFieldValue, F: FnOnce() -> String>(changes: &mut Vec<Change>, field: F, a: &T, b: &T, same: bool) {
+    if !same {
+        changes.push(Change {
+            field: field(),
+            a: a.to_field_value(),
+            b: b.to_field_value(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::Encoding;
+    use crate::recording::Axis;
+
+    // `mx` in 1/16 steps, 16 is 0
+    fn frame(delta: u16, mx: u8) -> Frame {
+        let mv = Move {
+            yaw: None,
+            pitch: None,
+            roll: None,
+            mx: Axis(mx),
+            my: Axis(16),
+            mz: Axis(16),
+            freelook: false,
+            triggers: Default::default(),
+        };
+        Frame {
+            moves: [Some(mv), None],
+            delta,
+        }
+    }
+
+    fn rec(frames: Vec<Frame>) -> Recording {
+        Recording {
+            mission: "marble/data/missions/beginner/movement.mis".to_string(),
+            frames,
+            mission_encoding: Encoding::Utf8,
+            framing: None,
+            metadata: None,
+        }
+    }
+
+    fn frames() -> Vec<Frame> {
+        (0..20).map(|i| frame(16 + i % 3, 16 + (i % 5) as u8)).collect()
+    }
+
+    fn paired(diff: &Diff) -> Vec<(Option<usize>, Option<usize>)> {
+        diff.frames.iter().map(|frame| (frame.index_a, frame.index_b)).collect()
+    }
+
+    #[test]
+    fn same_recs_have_no_differences() {
+        let difference = diff(&rec(frames()), &rec(frames()));
+        assert!(difference.is_same());
+        assert!(difference.frames.is_empty());
+    }
+
+    #[test]
+    fn split_frame_is_not_a_cascade() {
+        let mut b = frames();
+        b[5].delta = 8;
+        b.insert(6, frame(frames()[5].delta - 8, 24));
+        let difference = diff(&rec(frames()), &rec(b));
+        assert_eq!(paired(&difference), [(Some(5), Some(5)), (None, Some(6))]);
+        assert_eq!(difference.frames[0].changes[0].field, "delta");
+        assert_eq!(difference.frames[1].drift, 0);
+        assert_eq!(difference.first_divergence, Some(5));
+        assert_eq!(difference.past_end_b, 0);
+    }
+
+    #[test]
+    fn added_0_ms_frame_is_not_compared() {
+        let mut b = frames();
+        b.insert(5, frame(0, 24));
+        let difference = diff(&rec(frames()), &rec(b));
+        assert_eq!(paired(&difference), [(None, Some(5))]);
+    }
+
+    #[test]
+    fn merged_frames_are_not_a_cascade() {
+        let mut b = frames();
+        b[5].delta += b[6].delta;
+        b.remove(6);
+        let difference = diff(&rec(frames()), &rec(b));
+        assert_eq!(paired(&difference), [(Some(5), Some(5)), (Some(6), None)]);
+        assert_eq!(difference.past_end_a, 0);
+    }
+
+    // Everything after starts later in b, so it's compared against what b
+    // was doing at the same time rather than the frame it was shifted from
+    #[test]
+    fn longer_frame_compares_by_time() {
+        let mut a = frames();
+        for frame in &mut a {
+            frame.delta = 16;
+        }
+        let mut b = a.clone();
+        b[5].delta = 32;
+        let difference = diff(&rec(a), &rec(b));
+        assert_eq!(difference.frames[0].index_b, Some(5));
+        assert_eq!(difference.frames[1].index_a, Some(6));
+        assert_eq!(difference.frames[1].index_b, None);
+        assert_eq!(difference.frames[2].index_a, Some(7));
+        assert_eq!(difference.frames[2].index_b, Some(6));
+        // b runs 16 ms longer
+        assert_eq!(difference.past_end_b, 1);
+    }
+
+    #[test]
+    fn changed_frame_stays_paired() {
+        let mut b = frames();
+        b[3].moves[0].as_mut().unwrap().mx = Axis(32);
+        b.push(frame(16, 16));
+        let difference = diff(&rec(frames()), &rec(b));
+        assert_eq!(paired(&difference), [(Some(3), Some(3))]);
+        assert_eq!(difference.frames[0].changes[0].field, "moves[0].mx");
+        assert_eq!(difference.past_end_b, 1);
+        assert_eq!(difference.first_divergence, Some(3));
+    }
+}
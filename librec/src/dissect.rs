@@ -0,0 +1,357 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::vec;
+use core::fmt::Write;
+use serde::Serialize;
+use crate::bit_stream::{BitRead, BitReader};
+use crate::error::{Error, Result};
+pub use crate::field::{FieldValue, ToFieldValue};
+use crate::huffman::StringCodec;
+use crate::recording::{ReadOptions, Recording};
+
+// One named field and the bits it was read from. `start` and `end` are bit
+// offsets from the start of the file, `end` being exclusive.
+#[derive(Debug, Clone, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    pub start: usize,
+    pub end: usize,
+    pub value: FieldValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Field>,
+}
+
+impl Field {
+    pub fn len_bits(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+// Wraps a reader and records every field that gets read through it
+pub struct Dissector<R> {
+    inner: R,
+    position: usize,
+    open: Vec<Field>,
+    fields: Vec<Field>,
+}
+
+impl<R: BitRead> Dissector<R> {
+    pub fn new(inner: R) -> Dissector<R> {
+        Dissector {
+            inner,
+            position: 0,
+            open: vec![],
+            fields: vec![],
+        }
+    }
+
+    // Bits read so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn into_fields(mut self) -> Vec<Field> {
+        // Anything still open was cut short by an error
+        while !self.open.is_empty() {
+            self.close_field(FieldValue::Empty, None);
+        }
+        self.fields
+    }
+
+    fn close_field(&mut self, value: FieldValue, error: Option<String>) {
+        let mut field = match self.open.pop() {
+            Some(field) => field,
+            None => return,
+        };
+        field.end = self.position;
+        field.value = value;
+        // Only the innermost field gets the error, not every field around it
+        if field.children.iter().all(|child| child.error.is_none()) {
+            field.error = error;
+        }
+
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(field),
+            None => self.fields.push(field),
+        }
+    }
+}
+
+impl<R: BitRead> BitRead for Dissector<R> {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        let value = self.inner.read_bits_u8(bits)?;
+        self.position += bits as usize;
+        Ok(value)
+    }
+
+    fn read_bits(&mut self, bits: u8) -> Result<u64> {
+        let value = self.inner.read_bits(bits)?;
+        self.position += bits as usize;
+        Ok(value)
+    }
+
+    fn eof(&mut self) -> bool {
+        self.inner.eof()
+    }
+
+    fn begin_field(&mut self, name: &'static str) {
+        self.open.push(Field {
+            name,
+            start: self.position,
+            end: self.position,
+            value: FieldValue::Empty,
+            error: None,
+            children: vec![],
+        });
+    }
+
+    fn end_field(&mut self, value: core::result::Result<FieldValue, &Error>) {
+        match value {
+            Ok(value) => self.close_field(value, None),
+            Err(e) => self.close_field(FieldValue::Empty, Some(e.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Dissection {
+    #[serde(skip)]
+    pub data: Vec<u8>,
+    pub fields: Vec<Field>,
+    // Bits after the last field that nothing read
+    pub unread_bits: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub fn dissect(data: &[u8]) -> Dissection {
+    dissect_with(data, StringCodec::Raw)
+}
+
+// Parse a rec the same way Recording::from_stream_with does, keeping track of
+// every field along the way. Parse errors end up in the dissection instead of
+// being returned so the fields before them can still be looked at.
+pub fn dissect_with(data: &[u8], codec: StringCodec) -> Dissection {
+    let mut dissector = Dissector::new(BitReader::new(data));
+    let options = ReadOptions {
+        codec,
+        ..ReadOptions::default()
+    };
+    let error = Recording::from_stream_with(&mut dissector, &options)
+        .err()
+        .map(|e| e.to_string());
+    let read_bits = dissector.position();
+
+    Dissection {
+        data: data.to_vec(),
+        fields: dissector.into_fields(),
+        unread_bits: data.len() * 8 - read_bits,
+        error,
+    }
+}
+
+impl Dissection {
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // One line per field: where it starts (byte.bit), how many bits it takes,
+    // the raw bits, the path to the field and its decoded value.
+    pub fn hexdump(&self) -> String {
+        let mut out = String::new();
+        for (path, field) in indexed(&self.fields, "") {
+            self.dump_field(&mut out, &path, field);
+        }
+        if self.unread_bits > 0 {
+            let start = self.data.len() * 8 - self.unread_bits;
+            let _ = writeln!(
+                out,
+                "{:>6}.{}  {:>5}  {:<24}  (unread)",
+                start / 8,
+                start % 8,
+                self.unread_bits,
+                self.raw(start, self.data.len() * 8)
+            );
+        }
+        if let Some(error) = &self.error {
+            let _ = writeln!(out, "error: {}", error);
+        }
+        out
+    }
+
+    fn dump_field(&self, out: &mut String, path: &str, field: &Field) {
+        // Groups only get a header line, their bits are shown by the children
+        let raw = if field.children.is_empty() {
+            self.raw(field.start, field.end)
+        } else {
+            String::new()
+        };
+        let value = match &field.value {
+            FieldValue::Empty => String::new(),
+            value => format!(" = {}", value),
+        };
+        let _ = write!(
+            out,
+            "{:>6}.{}  {:>5}  {:<24}  {}{}",
+            field.start / 8,
+            field.start % 8,
+            field.len_bits(),
+            raw,
+            path,
+            value
+        );
+        if let Some(error) = &field.error {
+            let _ = write!(out, "  !! {}", error);
+        }
+        out.push('\n');
+
+        for (child_path, child) in indexed(&field.children, path) {
+            self.dump_field(out, &child_path, child);
+        }
+    }
+
+    // Short fields are shown as bits, most significant first so they read the
+    // same as the value. Anything longer is shown as the bytes it touches.
+    fn raw(&self, start: usize, end: usize) -> String {
+        if end - start <= 24 {
+            (start..end)
+                .rev()
+                .map(|bit| if self.bit(bit) { '1' } else { '0' })
+                .collect()
+        } else {
+            let bytes = &self.data[start / 8..end.div_ceil(8)];
+            let mut raw = bytes
+                .iter()
+                .take(7)
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if bytes.len() > 7 {
+                raw += " ..";
+            }
+            raw
+        }
+    }
+
+    fn bit(&self, bit: usize) -> bool {
+        self.data
+            .get(bit / 8)
+            .is_some_and(|byte| (byte >> (bit % 8)) & 1 == 1)
+    }
+}
+
+// Names of fields with their parents, with an index when a name repeats
+// among its siblings (block[3].frame.moves.move[0].yaw)
+fn indexed<'a>(fields: &'a [Field], parent: &str) -> Vec<(String, &'a Field)> {
+    let mut seen: Vec<(&str, usize)> = vec![];
+    fields
+        .iter()
+        .map(|field| {
+            let repeats = fields.iter().filter(|f| f.name == field.name).count() > 1;
+            let name = if repeats {
+                let index = match seen.iter_mut().find(|(name, _)| *name == field.name) {
+                    Some((_, count)) => {
+                        *count += 1;
+                        *count
+                    }
+                    None => {
+                        seen.push((field.name, 0));
+                        0
+                    }
+                };
+                format!("{}[{}]", field.name, index)
+            } else {
+                field.name.to_string()
+            };
+            let path = if parent.is_empty() {
+                name
+            } else {
+                format!("{}.{}", parent, name)
+            };
+            (path, field)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_stream::BitWriter;
+    use crate::encoding::Encoding;
+    use crate::recording::{Angle, Axis, Frame, Move, Triggers};
+
+    fn rec() -> Vec<u8> {
+        let mv = Move {
+            yaw: Some(Angle(0x4000)),
+            pitch: None,
+            roll: None,
+            mx: Axis(32),
+            my: Axis(32),
+            mz: Axis(32),
+            freelook: false,
+            triggers: Triggers::from_bits(1),
+        };
+        let recording = Recording {
+            mission: "a".to_string(),
+            frames: vec![
+                Frame { moves: [Some(mv), None], delta: 16 },
+                Frame { moves: [None, None], delta: 17 },
+            ],
+            mission_encoding: Encoding::Utf8,
+            framing: None,
+            metadata: None,
+        };
+        let mut bs = BitWriter::new();
+        recording.into_stream(&mut bs).unwrap();
+        bs.bytes()
+    }
+
+    fn find<'a>(fields: &'a [Field], path: &[&str]) -> &'a Field {
+        let field = fields.iter().find(|field| field.name == path[0]).unwrap();
+        match path.len() {
+            1 => field,
+            _ => find(&field.children, &path[1..]),
+        }
+    }
+
+    #[test]
+    fn fields_have_names_and_offsets() {
+        let data = rec();
+        let dissection = dissect(&data);
+        assert!(dissection.error.is_none());
+        assert_eq!(dissection.unread_bits, 0);
+
+        let names: Vec<&str> = dissection.fields.iter().map(|field| field.name).collect();
+        assert_eq!(names, ["mission", "block", "block"]);
+        let blocks = &dissection.fields[1..];
+        assert_eq!((blocks[0].start, blocks[0].end), (16, 80));
+        assert_eq!((blocks[1].start, blocks[1].end), (80, 120));
+
+        let yaw = find(&blocks[0].children, &["frame", "moves", "move", "yaw"]);
+        assert_eq!((yaw.start, yaw.end), (25, 42));
+        assert_eq!(yaw.children[0].name, "present");
+        let delta = find(&blocks[1].children, &["frame", "delta"]);
+        assert_eq!((delta.start, delta.end), (90, 100));
+        assert_eq!(delta.value.to_string(), "17");
+
+        let dump = dissection.hexdump();
+        assert!(dump.contains("block[0].frame.moves.move[0].yaw = "));
+        assert!(dump.contains("block[1].frame.moves.move[1].present = false"));
+    }
+
+    #[test]
+    fn truncated_block_leaves_unread_bits() {
+        let data = rec();
+        let dissection = dissect(&data[..data.len() - 2]);
+        // The second block stops after its delta, short of the padding
+        assert!(dissection.error.is_none());
+        assert_eq!(dissection.unread_bits, 4);
+        let block = &dissection.fields[2];
+        assert_eq!((block.start, block.end), (80, 100));
+        assert!(dissection.hexdump().contains("    12.4      4  0000                      (unread)"));
+    }
+}
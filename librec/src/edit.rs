@@ -0,0 +1,475 @@
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+use core::ops::Range;
+use crate::error::Result;
+use crate::error::ErrorKind::GenericError2;
+use crate::recording::{Frame, Framing, RawBits, Recording};
+
+// Cutting and splicing recs by game time. Times are in ms from the start of
+// the rec, and frame i runs from the total of the deltas before it until its
+// own delta has passed. A cut inside a frame splits its delta in two, and the
+// moves stay with the second half since the game only runs a frame's ticks
+// once its time is up. Times past the end of the rec mean the end.
+//
+// Recs with framing from a lossless read keep their blocks for every frame
+// that is left as it was, anything new gets the game's padding.
+impl Recording {
+    // Total of every frame's delta, in ms
+    pub fn duration(&self) -> u64 {
+        self.frames.iter().map(|frame| u64::from(frame.delta)).sum()
+    }
+
+    // Keep only what happens from `start_ms` until `end_ms`
+    pub fn trim(&mut self, start_ms: u64, end_ms: u64) -> Result<()> {
+        check_range(start_ms, end_ms)?;
+        let start = self.cut(start_ms);
+        let end = self.cut(end_ms);
+        self.split_off(end);
+        self.remove_at(0..start);
+        Ok(())
+    }
+
+    // Everything before `ms` and everything after it, as two recs of the same
+    // mission
+    pub fn split_at(mut self, ms: u64) -> (Recording, Recording) {
+        let at = self.cut(ms);
+        let rest = self.split_off(at);
+        (self, rest)
+    }
+
+    // Take out what happens from `start_ms` until `end_ms`, so everything
+    // after it happens that much sooner
+    pub fn remove_range(&mut self, start_ms: u64, end_ms: u64) -> Result<Vec<Frame>> {
+        check_range(start_ms, end_ms)?;
+        let start = self.cut(start_ms);
+        let end = self.cut(end_ms);
+        Ok(self.remove_at(start..end))
+    }
+
+    // Put `frames` in at `ms`, pushing everything after back by their deltas
+    pub fn insert_frames(&mut self, ms: u64, frames: Vec<Frame>) {
+        let at = self.cut(ms);
+        let count = frames.len();
+        self.insert_at(at, frames, None);
+        self.split_long_frames_in(at..at + count);
+    }
+
+    // Put all of `other` in at `ms`. Both have to be of the same mission.
+    pub fn splice(&mut self, ms: u64, other: Recording) -> Result<()> {
+        self.check_mission(&other)?;
+        let at = self.cut(ms);
+        self.splice_at(at, other);
+        Ok(())
+    }
+
+    // Add `other` onto the end. It ends the way `other` does if it has any
+    // frames, so a cut short last block carries over with it.
+    pub fn concat(&mut self, other: Recording) -> Result<()> {
+        self.check_mission(&other)?;
+        let end = match &other.framing {
+            _ if other.frames.is_empty() => None,
+            Some(framing) => Some((framing.terminated, framing.trailing.clone())),
+            None => Some((true, RawBits::default())),
+        };
+        self.splice_at(self.frames.len(), other);
+        if let (Some(framing), Some((terminated, trailing))) = (&mut self.framing, end) {
+            framing.terminated = terminated;
+            framing.trailing = trailing;
+        }
+        Ok(())
+    }
+
+    // Break up frames with deltas too long for the 10 bit field into several
+    // that add up to the same time, the moves going on the last of them
+    pub fn split_long_frames(&mut self) {
+        self.split_long_frames_in(0..self.frames.len());
+    }
+
+    fn split_long_frames_in(&mut self, range: Range<usize>) {
+        let (mut i, mut end) = (range.start, range.end);
+        while i < end {
+            let (waits, rest) = waits(self.frames[i].delta);
+            if !waits.is_empty() {
+                let count = waits.len();
+                self.frames[i].delta = rest;
+                self.insert_at(i, waits, None);
+                i += count;
+                end += count;
+            }
+            i += 1;
+        }
+    }
+
+    // Index of the first frame from `ms` on, after splitting the frame `ms`
+    // lands inside of
+    fn cut(&mut self, ms: u64) -> usize {
+        let mut start = 0u64;
+        for i in 0..self.frames.len() {
+            if start >= ms {
+                return i;
+            }
+            let delta = u64::from(self.frames[i].delta);
+            if start + delta > ms {
+                // Same size frame as before, so its block still lines up
+                let before = Frame {
+                    moves: [None, None],
+                    delta: (ms - start) as u16,
+                };
+                self.frames[i].delta -= before.delta;
+                self.insert_at(i, vec![before], None);
+                return i + 1;
+            }
+            start += delta;
+        }
+        self.frames.len()
+    }
+
+    // Frames from `at` on as a rec of their own, ending the way this one did.
+    // This one gets an end block in their place, unless there were none.
+    fn split_off(&mut self, at: usize) -> Recording {
+        let padding = self.padding_mut().map(|padding| padding.split_off(at));
+        let frames = self.frames.split_off(at);
+        let framing = self.framing.as_mut().map(|framing| {
+            let mut rest = Framing {
+                padding: padding.unwrap_or_default(),
+                terminated: true,
+                trailing: RawBits::default(),
+            };
+            if !rest.padding.is_empty() {
+                mem::swap(&mut framing.terminated, &mut rest.terminated);
+                mem::swap(&mut framing.trailing, &mut rest.trailing);
+            }
+            rest
+        });
+        Recording {
+            mission: self.mission.clone(),
+            frames,
+            mission_encoding: self.mission_encoding,
+            framing,
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    fn splice_at(&mut self, at: usize, mut other: Recording) {
+        if other.framing.is_some() && self.framing.is_none() {
+            self.framing = Some(Framing {
+                terminated: true,
+                ..Framing::default()
+            });
+        }
+        let count = other.frames.len();
+        let padding = other.padding_mut().map(mem::take);
+        self.insert_at(at, other.frames, padding);
+        self.split_long_frames_in(at..at + count);
+    }
+
+    // `padding` is the blocks the frames were read with, if there were any
+    fn insert_at(&mut self, at: usize, frames: Vec<Frame>, padding: Option<Vec<RawBits>>) {
+        if let Some(own) = self.padding_mut() {
+            let padding = match padding {
+                Some(padding) if padding.len() == frames.len() => padding,
+                _ => frames.iter().map(|frame| RawBits::game_padding(frame).unwrap_or_default()).collect(),
+            };
+            own.splice(at..at, padding);
+        }
+        self.frames.splice(at..at, frames);
+    }
+
+    fn remove_at(&mut self, range: Range<usize>) -> Vec<Frame> {
+        if let Some(padding) = self.padding_mut() {
+            padding.drain(range.clone());
+        }
+        self.frames.drain(range).collect()
+    }
+
+    // The framing's padding, lined up with the frames again if they were
+    // edited without it (as JSON, say)
+    fn padding_mut(&mut self) -> Option<&mut Vec<RawBits>> {
+        let frames = &self.frames;
+        let padding = &mut self.framing.as_mut()?.padding;
+        padding.truncate(frames.len());
+        for frame in &frames[padding.len()..] {
+            padding.push(RawBits::game_padding(frame).unwrap_or_default());
+        }
+        Some(padding)
+    }
+
+    // Frames from another rec only make sense played on the same level
+    fn check_mission(&self, other: &Recording) -> Result<()> {
+        if !same_mission(&self.mission, &other.mission) {
+            return Err(GenericError2(format!(
+                "Can't splice a rec of {:?} into a rec of {:?}",
+                other.mission, self.mission
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+fn check_range(start_ms: u64, end_ms: u64) -> Result<()> {
+    if start_ms > end_ms {
+        return Err(GenericError2(format!("Range starts at {} ms, after it ends at {} ms", start_ms, end_ms)).into());
+    }
+    Ok(())
+}
+
+// The game doesn't care about case or which way the slashes go
+fn same_mission(a: &str, b: &str) -> bool {
+    let normalize = |c: char| if c == '\\' { '/' } else { c.to_ascii_lowercase() };
+    a.trim().chars().map(normalize).eq(b.trim().chars().map(normalize))
+}
+
+// Empty frames of the longest delta there is, to go before a frame whose
+// delta is too long, and what is left of its delta after them
+fn waits(delta: u16) -> (Vec<Frame>, u16) {
+    let count = delta.saturating_sub(1) / Frame::MAX_DELTA;
+    let wait = Frame {
+        moves: [None, None],
+        delta: Frame::MAX_DELTA,
+    };
+    (vec![wait; count as usize], delta - count * Frame::MAX_DELTA)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use crate::bit_stream::{BitWrite, BitWriter};
+    use crate::encoding::Encoding;
+    use crate::quantize::Quantization;
+    use crate::recording::{write_block, Axis, Move, ReadOptions, Triggers};
+
+    const MISSION: &str = "marble/data/missions/beginner/movement.mis";
+
+    // A frame whose move says which one it is
+    fn frame(id: u8, delta: u16) -> Frame {
+        let mv = Move {
+            yaw: None,
+            pitch: None,
+            roll: None,
+            mx: Axis(id),
+            my: Axis(16),
+            mz: Axis(16),
+            freelook: false,
+            triggers: Triggers::empty(),
+        };
+        Frame {
+            moves: [Some(mv), None],
+            delta,
+        }
+    }
+
+    // Frame i runs from 0, 16, 33, 49 and 66 ms
+    fn rec() -> Recording {
+        Recording {
+            mission: MISSION.to_string(),
+            frames: (0..5).map(|i| frame(i, 16 + u16::from(i % 2))).collect(),
+            mission_encoding: Encoding::Utf8,
+            framing: None,
+            metadata: None,
+        }
+    }
+
+    // Which frame each one started out as, None for the new empty ones
+    fn ids(rec: &Recording) -> Vec<Option<u8>> {
+        rec.frames.iter().map(|frame| frame.first_move().map(|mv| mv.mx.0)).collect()
+    }
+
+    fn deltas(rec: &Recording) -> Vec<u16> {
+        rec.frames.iter().map(|frame| frame.delta).collect()
+    }
+
+    // The same rec as bytes, with a byte more in every block than the game
+    // would give it
+    fn odd_bytes(rec: &Recording) -> Vec<u8> {
+        let mut bs = BitWriter::new();
+        bs.write_string_bytes(rec.mission.as_bytes()).unwrap();
+        let mut inner = BitWriter::new();
+        for frame in &rec.frames {
+            let mut padding = RawBits::game_padding(frame).unwrap();
+            padding.bytes.push(0xA5);
+            padding.len_bits += 8;
+            write_block(&mut bs, &mut inner, frame, Some(&padding), Quantization::default()).unwrap();
+        }
+        bs.write_u8(0).unwrap();
+        bs.bytes()
+    }
+
+    fn lossless(bytes: &[u8]) -> Recording {
+        let options = ReadOptions {
+            lossless: true,
+            ..ReadOptions::default()
+        };
+        Recording::from_bytes_with(bytes, &options).unwrap()
+    }
+
+    #[test]
+    fn cut_splits_the_frame_it_lands_in() {
+        let mut rec = rec();
+        assert_eq!(rec.cut(20), 2);
+        assert_eq!(deltas(&rec), [16, 4, 13, 16, 17, 16]);
+        // The moves stay with the second half
+        assert_eq!(ids(&rec), [Some(0), None, Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(rec.duration(), 82);
+
+        // Frame boundaries and the end don't split anything
+        assert_eq!(rec.cut(33), 3);
+        assert_eq!(rec.cut(0), 0);
+        assert_eq!(rec.cut(500), 6);
+        assert_eq!(rec.frames.len(), 6);
+    }
+
+    #[test]
+    fn trim_keeps_the_middle() {
+        let mut rec = rec();
+        rec.trim(20, 50).unwrap();
+        assert_eq!(deltas(&rec), [13, 16, 1]);
+        assert_eq!(ids(&rec), [Some(1), Some(2), None]);
+        assert_eq!(rec.duration(), 30);
+
+        let mut whole = self::rec();
+        whole.trim(0, 1000).unwrap();
+        assert_eq!(deltas(&whole), deltas(&self::rec()));
+        assert!(whole.trim(10, 5).is_err());
+    }
+
+    #[test]
+    fn split_at_shares_out_the_time() {
+        let (a, b) = rec().split_at(20);
+        assert_eq!(deltas(&a), [16, 4]);
+        assert_eq!(deltas(&b), [13, 16, 17, 16]);
+        assert_eq!(ids(&b)[0], Some(1));
+        assert_eq!(a.duration() + b.duration(), rec().duration());
+        assert_eq!(b.mission, MISSION);
+
+        let (a, b) = rec().split_at(33);
+        assert_eq!(ids(&a), [Some(0), Some(1)]);
+        assert_eq!(ids(&b), [Some(2), Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn remove_range_pulls_the_rest_forward() {
+        let mut rec = rec();
+        let removed = rec.remove_range(16, 49).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(ids(&rec), [Some(0), Some(3), Some(4)]);
+        assert_eq!(rec.duration(), 82 - 33);
+
+        // Part of a frame leaves the rest of it behind
+        let mut rec = self::rec();
+        rec.remove_range(10, 20).unwrap();
+        assert_eq!(deltas(&rec), [10, 13, 16, 17, 16]);
+        assert!(rec.remove_range(20, 10).is_err());
+    }
+
+    #[test]
+    fn insert_frames_splits_long_deltas_into_waits() {
+        let mut rec = rec();
+        rec.insert_frames(33, vec![frame(9, 2500), frame(10, 16)]);
+        assert_eq!(deltas(&rec), [16, 17, 1023, 1023, 454, 16, 16, 17, 16]);
+        assert_eq!(ids(&rec), [Some(0), Some(1), None, None, Some(9), Some(10), Some(2), Some(3), Some(4)]);
+        assert_eq!(rec.duration(), 82 + 2516);
+        // Waits only go in front of the new frames
+        assert!(rec.frames.iter().all(|frame| frame.delta <= Frame::MAX_DELTA));
+    }
+
+    #[test]
+    fn waits_fill_whole_max_deltas() {
+        assert_eq!(waits(Frame::MAX_DELTA).0.len(), 0);
+        assert_eq!(waits(Frame::MAX_DELTA).1, Frame::MAX_DELTA);
+        assert_eq!(waits(Frame::MAX_DELTA + 1).0.len(), 1);
+        assert_eq!(waits(Frame::MAX_DELTA + 1).1, 1);
+        assert_eq!(waits(Frame::MAX_DELTA * 2).0.len(), 1);
+        assert_eq!(waits(Frame::MAX_DELTA * 2).1, Frame::MAX_DELTA);
+        assert_eq!(waits(0).0.len(), 0);
+
+        let mut rec = rec();
+        rec.frames[1].delta = 3000;
+        rec.split_long_frames();
+        assert_eq!(deltas(&rec), [16, 1023, 1023, 954, 16, 17, 16]);
+        assert_eq!(ids(&rec)[..4], [Some(0), None, None, Some(1)]);
+    }
+
+    #[test]
+    fn splice_puts_another_rec_in() {
+        let mut other = rec();
+        other.mission = "Marble\\Data\\Missions\\Beginner\\Movement.mis ".to_string();
+        other.frames.truncate(2);
+        for frame in &mut other.frames {
+            frame.moves[0].as_mut().unwrap().mx = Axis(40);
+        }
+
+        let mut rec = rec();
+        rec.splice(20, other.clone()).unwrap();
+        assert_eq!(deltas(&rec), [16, 4, 16, 17, 13, 16, 17, 16]);
+        assert_eq!(ids(&rec)[2..5], [Some(40), Some(40), Some(1)]);
+        assert_eq!(rec.mission, MISSION);
+
+        other.mission = "marble/data/missions/beginner/jump.mis".to_string();
+        assert!(rec.splice(0, other).is_err());
+    }
+
+    #[test]
+    fn concat_adds_onto_the_end() {
+        let mut rec = rec();
+        rec.concat(self::rec()).unwrap();
+        assert_eq!(rec.frames.len(), 10);
+        assert_eq!(rec.duration(), 164);
+        assert_eq!(ids(&rec)[5], Some(0));
+
+        let mut other = self::rec();
+        other.mission = String::from("marble/data/missions/beginner/jump.mis");
+        assert!(rec.concat(other).is_err());
+    }
+
+    #[test]
+    fn lossless_edits_keep_untouched_blocks() {
+        let bytes = odd_bytes(&rec());
+
+        // Doing nothing gives back every byte
+        let mut same = lossless(&bytes);
+        same.trim(0, same.duration()).unwrap();
+        assert_eq!(same.into_bytes().unwrap(), bytes);
+
+        let original = lossless(&bytes).framing.unwrap().padding;
+        let mut rec = lossless(&bytes);
+        rec.trim(20, 82).unwrap();
+        let framing = rec.framing.clone().unwrap();
+        assert_eq!(framing.padding.len(), rec.frames.len());
+        assert!(framing.terminated);
+        // The second half of a split frame is the same size, so it keeps its
+        // block too
+        assert_eq!(framing.padding[..], original[1..]);
+
+        // The new first half of a split frame gets the game's
+        let mut rec = lossless(&bytes);
+        rec.cut(20);
+        let framing = rec.framing.clone().unwrap();
+        assert_eq!(Some(&framing.padding[1]), RawBits::game_padding(&rec.frames[1]).as_ref());
+        assert_eq!(framing.padding[2..], original[1..]);
+
+        let back = lossless(&rec.into_bytes().unwrap());
+        assert_eq!(deltas(&back), [16, 4, 13, 16, 17, 16]);
+        assert_eq!(back.framing.unwrap().padding, framing.padding);
+    }
+
+    #[test]
+    fn lossless_concat_ends_like_the_other_rec() {
+        let mut a = lossless(&odd_bytes(&rec()));
+        let mut b_bytes = odd_bytes(&rec());
+        // No end block, but a couple of stray bytes instead
+        b_bytes.pop();
+        b_bytes.extend_from_slice(&[7, 1]);
+        let b = lossless(&b_bytes);
+        let b_padding = b.framing.clone().unwrap().padding;
+        a.concat(b).unwrap();
+
+        let framing = a.framing.unwrap();
+        assert!(!framing.terminated);
+        assert_eq!(framing.trailing.bytes, [7, 1]);
+        assert_eq!(framing.padding[5..], b_padding[..]);
+    }
+}
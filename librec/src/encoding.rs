@@ -0,0 +1,199 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+use crate::field::{FieldValue, ToFieldValue};
+use crate::error::Result;
+use crate::error::ErrorKind::GenericError2;
+
+// Code page used for the bytes of a string in a rec. Older versions of the
+// game wrote whatever the system code page was, usually Windows-1252.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Latin1,
+    Windows1252,
+}
+
+// Windows-1252 characters for 0x80-0x9F. The five bytes it leaves undefined
+// map to the C1 controls like Latin-1 so every byte still round trips.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+impl Encoding {
+    // Anything that is valid UTF-8 is taken as UTF-8 (that includes plain
+    // ASCII). Otherwise it's a single byte code page, and bytes in 0x80-0x9F
+    // are only printable in Windows-1252.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        if core::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else if bytes.iter().any(|&b| (0x80..0xA0).contains(&b)) {
+            Encoding::Windows1252
+        } else {
+            Encoding::Latin1
+        }
+    }
+
+    pub fn detect_and_decode(bytes: &[u8]) -> Result<(String, Encoding)> {
+        let encoding = Encoding::detect(bytes);
+        Ok((encoding.decode(bytes)?, encoding))
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        *self == Encoding::Utf8
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<String> {
+        match self {
+            Encoding::Utf8 => Ok(String::from_utf8(bytes.to_vec())?),
+            Encoding::Latin1 => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+            Encoding::Windows1252 => Ok(bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    _ => char::from(b),
+                })
+                .collect()),
+        }
+    }
+
+    pub fn encode(&self, value: &str) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Ok(value.as_bytes().to_vec()),
+            Encoding::Latin1 => value
+                .chars()
+                .map(|ch| match ch as u32 {
+                    code @ 0..=0xFF => Ok(code as u8),
+                    _ => Err(self.unencodable(ch)),
+                })
+                .collect(),
+            Encoding::Windows1252 => value
+                .chars()
+                .map(|ch| match ch as u32 {
+                    code @ (0..=0x7F | 0xA0..=0xFF) => Ok(code as u8),
+                    _ => WINDOWS_1252_HIGH
+                        .iter()
+                        .position(|&high| high == ch)
+                        .map(|i| 0x80 + i as u8)
+                        .ok_or_else(|| self.unencodable(ch)),
+                })
+                .collect(),
+        }
+    }
+
+    fn unencodable(&self, ch: char) -> crate::error::Error {
+        GenericError2(format!("Character {:?} cannot be written as {:?}", ch, self)).into()
+    }
+}
+
+// A string read along with the code page it was in
+impl ToFieldValue for (String, Encoding) {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Text(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use crate::bit_stream::{BitRead, BitReader, BitWrite, BitWriter};
+    use crate::huffman::StringCodec;
+    use crate::recording::Recording;
+
+    #[test]
+    fn detection_goes_utf8_then_windows_1252_then_latin1() {
+        assert_eq!(Encoding::detect(b"marble/data/missions/beginner/movement.mis"), Encoding::Utf8);
+        assert_eq!(Encoding::detect("missions/caf\u{e9}.mis".as_bytes()), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b""), Encoding::Utf8);
+        // 0x80-0x9F only mean something in Windows-1252
+        assert_eq!(Encoding::detect(b"missions/\x93quoted\x94.mis"), Encoding::Windows1252);
+        assert_eq!(Encoding::detect(b"missions/caf\xe9 \x80.mis"), Encoding::Windows1252);
+        assert_eq!(Encoding::detect(b"missions/caf\xe9.mis"), Encoding::Latin1);
+        // Bytes that happen to be valid UTF-8 are taken as it
+        assert_eq!(Encoding::detect(b"missions/\xc3\xa9.mis"), Encoding::Utf8);
+    }
+
+    #[test]
+    fn every_byte_round_trips() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for &encoding in &[Encoding::Latin1, Encoding::Windows1252] {
+            let decoded = encoding.decode(&bytes).unwrap();
+            assert_eq!(encoding.encode(&decoded).unwrap(), bytes, "{:?}", encoding);
+        }
+        assert_eq!(Encoding::Windows1252.decode(b"\x80\x99").unwrap(), "\u{20AC}\u{2122}");
+        assert_eq!(Encoding::Latin1.decode(b"\x80\xe9").unwrap(), "\u{80}\u{e9}");
+        assert!(Encoding::Latin1.encode("\u{20AC}").is_err());
+        assert!(Encoding::Windows1252.encode("\u{100}").is_err());
+        assert!(Encoding::Utf8.decode(b"\xe9").is_err());
+    }
+
+    fn rec_bytes(mission: &[u8]) -> Vec<u8> {
+        let mut bs = BitWriter::new();
+        bs.write_string_bytes(mission).unwrap();
+        // One 16 ms frame without moves
+        for &byte in &[4u8, 0x40, 0, 0, 0] {
+            bs.write_u8(byte).unwrap();
+        }
+        bs.bytes()
+    }
+
+    #[test]
+    fn mission_names_keep_their_code_page() {
+        let cases: [(&[u8], &str, Encoding); 3] = [
+            (b"missions/\x93Sp\xe9cial\x94.mis", "missions/\u{201C}Sp\u{e9}cial\u{201D}.mis", Encoding::Windows1252),
+            (b"missions/Sp\xe9cial.mis", "missions/Sp\u{e9}cial.mis", Encoding::Latin1),
+            ("missions/Sp\u{e9}cial.mis".as_bytes(), "missions/Sp\u{e9}cial.mis", Encoding::Utf8),
+        ];
+        for &(raw, mission, encoding) in cases.iter() {
+            let bytes = rec_bytes(raw);
+            let rec = Recording::from_bytes(&bytes).unwrap();
+            assert_eq!(rec.mission, mission);
+            assert_eq!(rec.mission_encoding, encoding);
+            assert_eq!(rec.into_bytes().unwrap(), bytes, "{:?}", encoding);
+        }
+
+        // Huffman coded strings go through the same code pages
+        let mut bs = BitWriter::new();
+        bs.write_encoded_string_with("Sp\u{e9}cial", Encoding::Latin1, StringCodec::Huffman).unwrap();
+        let bytes = bs.bytes();
+        let (value, encoding) = BitReader::new(&bytes).read_encoded_string_with(StringCodec::Huffman).unwrap();
+        assert_eq!((value.as_str(), encoding), ("Sp\u{e9}cial", Encoding::Latin1));
+    }
+
+    #[test]
+    fn strings_longer_than_the_length_byte_are_errors() {
+        let mut bs = BitWriter::new();
+        assert!(bs.write_string_bytes(&[b'a'; 255]).is_ok());
+        let mut bs = BitWriter::new();
+        let error = bs.write_string_bytes(&[b'a'; 256]).unwrap_err();
+        assert!(error.to_string().contains("too long"), "{}", error);
+        // Nothing of it got written
+        assert_eq!(bs.len_bits(), 0);
+
+        // It's the encoded length that counts: 200 e-acutes are 200 bytes in
+        // Latin-1 but 400 in UTF-8
+        let mission: String = vec!['\u{e9}'; 200].into_iter().collect();
+        for &(encoding, fits) in &[(Encoding::Latin1, true), (Encoding::Windows1252, true), (Encoding::Utf8, false)] {
+            let mut bs = BitWriter::new();
+            let written = bs.write_encoded_string_with(&mission, encoding, StringCodec::Raw);
+            assert_eq!(written.is_ok(), fits, "{:?}", encoding);
+        }
+        let rec = Recording {
+            mission: mission.clone(),
+            frames: vec![],
+            mission_encoding: Encoding::Utf8,
+            framing: None,
+            metadata: None,
+        };
+        assert!(rec.into_bytes().is_err());
+        let mut bs = BitWriter::new();
+        assert!(bs.write_encoded_string_with(&mission.repeat(2), Encoding::Latin1, StringCodec::Huffman).is_err());
+    }
+}
@@ -1,24 +1,223 @@
+use alloc::boxed::Box;
+use alloc::string::{FromUtf8Error, String, ToString};
+use core::fmt;
 
-error_chain! {
-    types {
-        Error, ErrorKind, ResultExt, Result;
+#[derive(Debug)]
+pub enum ErrorKind {
+    // Context added with ResultExt::chain_err
+    Msg(String),
+    Fmt(fmt::Error),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+    FromUtf8(FromUtf8Error),
+    GenericError(&'static str),
+    GenericError2(String),
+    ReadEof,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    // The error this one was chained onto, see ResultExt
+    cause: Option<Box<Error>>,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> ErrorKind {
+        self.kind
+    }
+
+    // The error underneath this one, if it was made with chain_err
+    pub fn cause(&self) -> Option<&Error> {
+        self.cause.as_deref()
+    }
+
+    // This error and then every one underneath it
+    pub fn iter(&self) -> impl Iterator<Item = &Error> {
+        core::iter::successors(Some(self), |error| error.cause())
+    }
+
+    // The whole chain on one line, outermost first
+    pub fn display_chain(&self) -> String {
+        let mut out = self.to_string();
+        for cause in self.iter().skip(1) {
+            out += ": ";
+            out += &cause.to_string();
+        }
+        out
+    }
+
+    // Lets callers tell a broken reader/writer apart from a broken rec,
+    // whatever context was chained on top
+    pub fn is_io(&self) -> bool {
+        #[cfg(feature = "std")]
+        {
+            self.iter().any(|error| matches!(error.kind, ErrorKind::Io(_)))
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            false
+        }
     }
 
-    foreign_links {
-        Fmt(::std::fmt::Error);
-        Io(::std::io::Error);
-        Json(::serde_json::Error);
-        FromUtf8(::std::string::FromUtf8Error);
+    pub fn is_eof(&self) -> bool {
+        self.iter().any(|error| matches!(error.kind, ErrorKind::ReadEof))
     }
+}
 
-    errors {
-        GenericError(t: &'static str) {
-            description("Generic error")
-            display("Generic error: {}", t)
+// What error-chain's ResultExt did: wrap an error in one that says what was
+// being done at the time, keeping the original as its cause
+pub trait ResultExt<T> {
+    fn chain_err<F, K>(self, context: F) -> Result<T>
+    where
+        F: FnOnce() -> K,
+        K: Into<ErrorKind>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for core::result::Result<T, E> {
+    fn chain_err<F, K>(self, context: F) -> Result<T>
+    where
+        F: FnOnce() -> K,
+        K: Into<ErrorKind>,
+    {
+        self.map_err(|error| Error {
+            kind: context().into(),
+            cause: Some(Box::new(error.into())),
+        })
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Msg(t) => write!(f, "{}", t),
+            ErrorKind::Fmt(e) => e.fmt(f),
+            #[cfg(feature = "std")]
+            ErrorKind::Io(e) => e.fmt(f),
+            #[cfg(feature = "json")]
+            ErrorKind::Json(e) => e.fmt(f),
+            ErrorKind::FromUtf8(e) => e.fmt(f),
+            ErrorKind::GenericError(t) => write!(f, "Generic error: {}", t),
+            ErrorKind::GenericError2(t) => write!(f, "Generic error: {}", t),
+            ErrorKind::ReadEof => write!(f, "Read EOF"),
         }
-        GenericError2(t: String) {
-            description("Generic error")
-            display("Generic error: {}", t)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Some(cause) = &self.cause {
+            return Some(cause.as_ref());
         }
+        match &self.kind {
+            ErrorKind::Io(e) => Some(e),
+            #[cfg(feature = "json")]
+            ErrorKind::Json(e) => Some(e),
+            ErrorKind::FromUtf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error { kind, cause: None }
+    }
+}
+
+impl From<&str> for ErrorKind {
+    fn from(message: &str) -> ErrorKind {
+        ErrorKind::Msg(message.to_string())
+    }
+}
+
+impl From<String> for ErrorKind {
+    fn from(message: String) -> ErrorKind {
+        ErrorKind::Msg(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Error {
+        ErrorKind::from(message).into()
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        ErrorKind::from(message).into()
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(e: fmt::Error) -> Error {
+        ErrorKind::Fmt(e).into()
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        ErrorKind::Io(e).into()
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        ErrorKind::Json(e).into()
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Error {
+        ErrorKind::FromUtf8(e).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind::ReadEof;
+
+    fn read() -> Result<u8> {
+        Err(ReadEof.into())
+    }
+
+    #[test]
+    fn chain_err_keeps_the_cause() {
+        let error = read().chain_err(|| "Reading frame 3").unwrap_err();
+        assert_eq!(error.to_string(), "Reading frame 3");
+        assert!(error.is_eof());
+        assert!(matches!(error.cause().map(Error::kind), Some(ReadEof)));
+        assert_eq!(error.display_chain(), "Reading frame 3: Read EOF");
+
+        let error = Err::<(), _>(error).chain_err(|| ErrorKind::GenericError("Bad rec")).unwrap_err();
+        assert_eq!(error.iter().count(), 3);
+        assert_eq!(error.display_chain(), "Generic error: Bad rec: Reading frame 3: Read EOF");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_errors_show_through_context() {
+        let io = std::io::Error::other("disk on fire");
+        let error = Err::<(), _>(io).chain_err(|| "Reading rec").unwrap_err();
+        assert!(error.is_io());
+        assert!(std::error::Error::source(&error).is_some());
     }
 }
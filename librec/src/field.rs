@@ -0,0 +1,104 @@
+use alloc::string::String;
+use core::fmt;
+use serde::Serialize;
+
+// What BitRead::read_field hands to the begin_field/end_field hooks. Lives on
+// its own so the bit streams don't need the dissector to know about them.
+
+// Decoded value of a single field. Fields that are just groups of other
+// fields (frames, moves, blocks) have no value of their own.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Empty,
+    Bool(bool),
+    Int(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldValue::Empty => write!(f, "none"),
+            FieldValue::Bool(value) => write!(f, "{}", value),
+            FieldValue::Int(value) => write!(f, "{}", value),
+            FieldValue::Float(value) => write!(f, "{}", value),
+            FieldValue::Text(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+pub trait ToFieldValue {
+    fn to_field_value(&self) -> FieldValue;
+}
+
+impl ToFieldValue for () {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Empty
+    }
+}
+
+impl ToFieldValue for bool {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Bool(*self)
+    }
+}
+
+impl ToFieldValue for u8 {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Int(u64::from(*self))
+    }
+}
+
+impl ToFieldValue for u16 {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Int(u64::from(*self))
+    }
+}
+
+impl ToFieldValue for u32 {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Int(u64::from(*self))
+    }
+}
+
+impl ToFieldValue for u64 {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Int(*self)
+    }
+}
+
+impl ToFieldValue for f32 {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Float(f64::from(*self))
+    }
+}
+
+impl ToFieldValue for f64 {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Float(*self)
+    }
+}
+
+impl ToFieldValue for String {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Text(self.clone())
+    }
+}
+
+// Arrays are groups, every item is read as a field of its own
+impl<T, const N: usize> ToFieldValue for [T; N] {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Empty
+    }
+}
+
+impl<T: ToFieldValue> ToFieldValue for Option<T> {
+    fn to_field_value(&self) -> FieldValue {
+        match self {
+            Some(value) => value.to_field_value(),
+            None => FieldValue::Empty,
+        }
+    }
+}
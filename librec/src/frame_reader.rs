@@ -0,0 +1,253 @@
+use alloc::string::String;
+use crate::bit_stream::{BitCounter, BitRead};
+use crate::encoding::Encoding;
+use crate::error::Result;
+use crate::huffman::StringCodec;
+use crate::recording::{Block, Frame, Recording};
+
+// A frame as it comes out of a FrameReader
+#[derive(Debug, Clone)]
+pub struct FrameEntry {
+    pub index: usize,
+    // Byte the frame's block starts at, counting from the start of the rec
+    pub offset: usize,
+    // Milliseconds from the start of the rec to the end of this frame
+    pub elapsed: u64,
+    pub frame: Frame,
+}
+
+// Reads a rec one block at a time instead of all at once like
+// Recording::from_stream. The mission is read up front, frames are handed out
+// as they are read and never kept, so recs can be cut short or streamed in
+// from somewhere without holding the whole thing in memory.
+pub struct FrameReader<R> {
+    bs: BitCounter<R>,
+    mission: String,
+    mission_encoding: Encoding,
+    index: usize,
+    elapsed: u64,
+    done: bool,
+}
+
+impl<R: BitRead> FrameReader<R> {
+    pub fn new(bs: R) -> Result<FrameReader<R>> {
+        FrameReader::with_codec(bs, StringCodec::Raw)
+    }
+
+    pub fn with_codec(bs: R, codec: StringCodec) -> Result<FrameReader<R>> {
+        let mut bs = BitCounter::new(bs);
+        let (mission, mission_encoding) = bs.read_field("mission", |bs| bs.read_encoded_string_with(codec))?;
+        Ok(FrameReader {
+            bs,
+            mission,
+            mission_encoding,
+            index: 0,
+            elapsed: 0,
+            done: false,
+        })
+    }
+
+    pub fn mission(&self) -> &str {
+        &self.mission
+    }
+
+    pub fn mission_encoding(&self) -> Encoding {
+        self.mission_encoding
+    }
+
+    // Bytes read so far, for showing progress
+    pub fn bytes_read(&self) -> usize {
+        self.bs.position() / 8
+    }
+
+    // Milliseconds covered by the frames read so far
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    pub fn into_inner(self) -> R {
+        self.bs.into_inner()
+    }
+}
+
+impl<R: BitRead> Iterator for FrameReader<R> {
+    type Item = Result<FrameEntry>;
+
+    // Stops for good after the end block, a cut short block or an error
+    fn next(&mut self) -> Option<Result<FrameEntry>> {
+        if self.done || self.bs.eof() {
+            self.done = true;
+            return None;
+        }
+
+        let offset = self.bytes_read();
+        match self.bs.read_field("block", |bs| Recording::read_block(bs, false)) {
+            Ok(Block::Frame(frame, _)) => {
+                self.elapsed += u64::from(frame.delta);
+                let entry = FrameEntry {
+                    index: self.index,
+                    offset,
+                    elapsed: self.elapsed,
+                    frame,
+                };
+                self.index += 1;
+                Some(Ok(entry))
+            }
+            Ok(Block::End) | Ok(Block::Truncated) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use crate::bit_stream::{BitReader, BitWrite, BitWriter};
+    use crate::quantize::Quantization;
+    use crate::recording::{write_block, Angle, Axis, Move, Triggers};
+
+    const MISSION: &[u8] = b"marble/data/missions/beginner/movement.mis";
+
+    // Blocks of a few different sizes, so the offsets aren't a pattern
+    fn frames() -> Vec<Frame> {
+        (0..12u16)
+            .map(|i| {
+                let mv = Move {
+                    yaw: if i % 3 == 0 { Some(Angle(i * 1000)) } else { None },
+                    pitch: if i % 4 == 0 { Some(Angle(7)) } else { None },
+                    roll: None,
+                    mx: Axis(i as u8),
+                    my: Axis(16),
+                    mz: Axis(16),
+                    freelook: true,
+                    triggers: Triggers::empty(),
+                };
+                Frame {
+                    moves: [if i % 5 == 4 { None } else { Some(mv) }, if i % 2 == 0 { Some(mv) } else { None }],
+                    delta: 10 + i * 7,
+                }
+            })
+            .collect()
+    }
+
+    fn bytes(frames: &[Frame]) -> Vec<u8> {
+        let mut bs = BitWriter::new();
+        bs.write_string_bytes(MISSION).unwrap();
+        let mut inner = BitWriter::new();
+        for frame in frames {
+            write_block(&mut bs, &mut inner, frame, None, Quantization::default()).unwrap();
+        }
+        bs.bytes()
+    }
+
+    // Where each block starts, going by the length bytes
+    fn block_offsets(bytes: &[u8], blocks: usize) -> Vec<usize> {
+        let mut offsets = vec![1 + MISSION.len()];
+        while offsets.len() < blocks {
+            let last = *offsets.last().unwrap();
+            offsets.push(last + 1 + bytes[last] as usize);
+        }
+        offsets
+    }
+
+    fn same_frames(a: &[Frame], b: &[Frame]) {
+        assert_eq!(a.len(), b.len());
+        for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+            assert_eq!((a.moves, a.delta), (b.moves, b.delta), "frame {}", i);
+        }
+    }
+
+    fn read_all(bytes: &[u8]) -> Vec<FrameEntry> {
+        FrameReader::new(BitReader::new(bytes)).unwrap().map(|entry| entry.unwrap()).collect()
+    }
+
+    #[test]
+    fn reads_the_same_frames_as_a_recording() {
+        let bytes = bytes(&frames());
+        let entries = read_all(&bytes);
+        let rec = Recording::from_stream(&mut BitReader::new(&bytes)).unwrap();
+        let read: Vec<Frame> = entries.iter().map(|entry| entry.frame.clone()).collect();
+        same_frames(&read, &rec.frames);
+
+        let offsets = block_offsets(&bytes, rec.frames.len());
+        let mut elapsed = 0;
+        for (i, entry) in entries.iter().enumerate() {
+            elapsed += u64::from(rec.frames[i].delta);
+            assert_eq!(entry.index, i);
+            assert_eq!(entry.offset, offsets[i], "frame {}", i);
+            assert_eq!(entry.elapsed, elapsed);
+        }
+        assert_eq!(elapsed, rec.duration());
+    }
+
+    #[test]
+    fn stopping_early_leaves_the_rest_unread() {
+        let bytes = bytes(&frames());
+        let offsets = block_offsets(&bytes, 12);
+        let mut reader = FrameReader::new(BitReader::new(&bytes)).unwrap();
+        assert_eq!(reader.mission().as_bytes(), MISSION);
+        assert_eq!(reader.bytes_read(), offsets[0]);
+        assert_eq!(reader.elapsed(), 0);
+
+        let first: Vec<FrameEntry> = reader.by_ref().take(3).map(|entry| entry.unwrap()).collect();
+        assert_eq!(first.len(), 3);
+        assert_eq!(reader.bytes_read(), offsets[3]);
+        assert_eq!(reader.elapsed(), 10 + 17 + 24);
+
+        // Picks up where it left off
+        let next = reader.next().unwrap().unwrap();
+        assert_eq!((next.index, next.offset), (3, offsets[3]));
+    }
+
+    #[test]
+    fn stops_at_the_end_block() {
+        let mut bytes = bytes(&frames()[..4]);
+        bytes.push(0);
+        // Looks like another block, but comes after the end
+        bytes.extend_from_slice(&[4, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let mut reader = FrameReader::new(BitReader::new(&bytes)).unwrap();
+        assert_eq!(reader.by_ref().count(), 4);
+        assert!(reader.next().is_none());
+        same_frames(&Recording::from_bytes(&bytes).unwrap().frames, &frames()[..4]);
+    }
+
+    #[test]
+    fn a_truncated_stream_keeps_the_whole_blocks() {
+        let full = bytes(&frames());
+        let offsets = block_offsets(&full, 12);
+        // Part way into the last block's frame, then part way into its length
+        for &cut in &[full.len() - 2, offsets[11] + 1, offsets[11]] {
+            let bytes = &full[..cut];
+            let entries = read_all(bytes);
+            assert_eq!(entries.len(), 11, "cut at {}", cut);
+            let rec = Recording::from_bytes(bytes).unwrap();
+            let read: Vec<Frame> = entries.into_iter().map(|entry| entry.frame).collect();
+            same_frames(&read, &rec.frames);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reads_from_io() {
+        use crate::bit_io::IoBitReader;
+
+        let bytes = bytes(&frames());
+        let entries: Vec<FrameEntry> = FrameReader::new(IoBitReader::from_reader(&bytes[..]))
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .collect();
+        let from_slice = read_all(&bytes);
+        assert_eq!(entries.len(), from_slice.len());
+        for (a, b) in entries.iter().zip(from_slice.iter()) {
+            assert_eq!((a.offset, a.elapsed, a.frame.moves), (b.offset, b.elapsed, b.frame.moves));
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use alloc::vec::Vec;
+use crate::bit_stream::{BitRead, BitWrite};
+use crate::error::Result;
+use crate::error::ErrorKind::GenericError;
+use serde::{Serialize, Deserialize};
+
+// How strings are laid out in a stream. `Raw` is what MBG recs use for the
+// mission path, `Huffman` is Torque's BitStream::readString/writeString.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StringCodec {
+    #[default]
+    Raw,
+    Huffman,
+}
+
+// HuffmanProcessor::csm_charFreqs from Torque
+const CHAR_FREQS: [u32; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 329, 21, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    2809, 68, 0, 27, 0, 58, 3, 62, 4, 7, 0, 0, 15, 65, 554, 3,
+    394, 404, 189, 117, 30, 51, 27, 15, 34, 32, 80, 1, 142, 3, 142, 39,
+    0, 144, 125, 44, 122, 275, 70, 135, 61, 127, 8, 12, 113, 246, 122, 36,
+    185, 1, 149, 309, 335, 12, 11, 14, 54, 151, 0, 0, 2, 0, 0, 211,
+    0, 2090, 344, 736, 993, 2872, 701, 605, 646, 1552, 328, 305, 1240, 735, 1533, 1713,
+    562, 3, 1775, 1149, 1469, 979, 407, 553, 59, 279, 31, 0, 0, 0, 68, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+#[derive(Clone, Copy)]
+struct Leaf {
+    bits: u8,
+    code: u32,
+}
+
+// Children follow Torque's indexing: >= 0 is a node, < 0 is leaf -(i + 1)
+#[derive(Clone, Copy)]
+struct Node {
+    pop: u32,
+    index0: i16,
+    index1: i16,
+}
+
+struct HuffmanTree {
+    leaves: [Leaf; 256],
+    nodes: [Node; 256],
+}
+
+// The tree never changes, so build it at compile time
+static TREE: HuffmanTree = HuffmanTree::build();
+
+impl HuffmanTree {
+    // Same steps as HuffmanProcessor::buildTables, including how it picks and
+    // removes entries, so the codes come out identical
+    const fn build() -> HuffmanTree {
+        let mut nodes = [Node { pop: 0, index0: 0, index1: 0 }; 256];
+        let mut node_count = 1;
+
+        let mut wraps = [0i16; 256];
+        let mut wrap_pops = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            wraps[i] = -(i as i16 + 1);
+            wrap_pops[i] = CHAR_FREQS[i] + 1;
+            i += 1;
+        }
+
+        let mut curr_wraps = 256;
+        while curr_wraps != 1 {
+            let mut min1 = 0xffff_fffe;
+            let mut min2 = 0xffff_ffff;
+            let mut index1 = 0;
+            let mut index2 = 0;
+
+            i = 0;
+            while i < curr_wraps {
+                if wrap_pops[i] < min1 {
+                    min2 = min1;
+                    index2 = index1;
+                    min1 = wrap_pops[i];
+                    index1 = i;
+                } else if wrap_pops[i] < min2 {
+                    min2 = wrap_pops[i];
+                    index2 = i;
+                }
+                i += 1;
+            }
+
+            nodes[node_count] = Node {
+                pop: wrap_pops[index1] + wrap_pops[index2],
+                index0: wraps[index1],
+                index1: wraps[index2],
+            };
+
+            let (merge_index, nuke_index) = if index1 > index2 {
+                (index2, index1)
+            } else {
+                (index1, index2)
+            };
+            wraps[merge_index] = node_count as i16;
+            wrap_pops[merge_index] = nodes[node_count].pop;
+            node_count += 1;
+
+            if index2 != curr_wraps - 1 {
+                wraps[nuke_index] = wraps[curr_wraps - 1];
+                wrap_pops[nuke_index] = wrap_pops[curr_wraps - 1];
+            }
+            curr_wraps -= 1;
+        }
+
+        // The root has to be node 0
+        nodes[0] = nodes[wraps[0] as usize];
+
+        // generateCodes, with an explicit stack instead of recursion. Codes are
+        // the path from the root, first step in the lowest bit.
+        let mut leaves = [Leaf { bits: 0, code: 0 }; 256];
+        let mut stack = [(0i16, 0u8, 0u32); 256];
+        let mut depth = 1;
+        while depth > 0 {
+            depth -= 1;
+            let (index, bits, code) = stack[depth];
+            if index < 0 {
+                leaves[(-(index + 1)) as usize] = Leaf { bits, code };
+            } else {
+                let node = nodes[index as usize];
+                stack[depth] = (node.index0, bits + 1, code);
+                stack[depth + 1] = (node.index1, bits + 1, code | (1 << bits));
+                depth += 2;
+            }
+        }
+
+        HuffmanTree { leaves, nodes }
+    }
+}
+
+pub(crate) fn too_long() -> crate::error::Error {
+    GenericError("String too long, must be under 256 bytes").into()
+}
+
+// HuffmanProcessor::readHuffBuffer: a flag for compressed or not, then an
+// 8 bit length and either Huffman codes or raw bytes
+pub fn read_huffman_bytes<R: BitRead + ?Sized>(bs: &mut R) -> Result<Vec<u8>> {
+    let compressed = bs.read_flag()?;
+    let length = bs.read_u8()? as usize;
+    let mut bytes = Vec::with_capacity(length);
+
+    for _ in 0..length {
+        if compressed {
+            let mut index = 0i16;
+            while index >= 0 {
+                let node = &TREE.nodes[index as usize];
+                index = if bs.read_flag()? { node.index1 } else { node.index0 };
+            }
+            bytes.push((-(index + 1)) as u8);
+        } else {
+            bytes.push(bs.read_u8()?);
+        }
+    }
+    Ok(bytes)
+}
+
+// HuffmanProcessor::writeHuffBuffer, falls back to raw bytes whenever the
+// codes would not actually be any shorter
+pub fn write_huffman_bytes<W: BitWrite + ?Sized>(bs: &mut W, bytes: &[u8]) -> Result<()> {
+    if bytes.len() > 255 {
+        return Err(too_long());
+    }
+
+    let bits: usize = bytes.iter().map(|&b| TREE.leaves[b as usize].bits as usize).sum();
+    if bits >= bytes.len() * 8 {
+        bs.write_flag(false)?;
+        bs.write_u8(bytes.len() as u8)?;
+        for &byte in bytes {
+            bs.write_u8(byte)?;
+        }
+    } else {
+        bs.write_flag(true)?;
+        bs.write_u8(bytes.len() as u8)?;
+        for &byte in bytes {
+            let leaf = &TREE.leaves[byte as usize];
+            bs.write_bits_u32(leaf.code, leaf.bits)?;
+        }
+    }
+    Ok(())
+}
+
+// BitStream::readString with a stringBuffer set: if the flag is set, the
+// string starts with the first `offset` bytes of the previous one
+pub fn read_buffered_bytes<R: BitRead + ?Sized>(bs: &mut R, buffer: &mut Vec<u8>) -> Result<Vec<u8>> {
+    let bytes = if bs.read_flag()? {
+        let offset = bs.read_u8()? as usize;
+        if offset > buffer.len() {
+            return Err(GenericError("String prefix longer than previous string").into());
+        }
+        let mut bytes = buffer[..offset].to_vec();
+        bytes.extend(read_huffman_bytes(bs)?);
+        bytes
+    } else {
+        read_huffman_bytes(bs)?
+    };
+    buffer.clone_from(&bytes);
+    Ok(bytes)
+}
+
+// BitStream::writeString with a stringBuffer set, only bothers sharing a
+// prefix when more than 2 bytes match
+pub fn write_buffered_bytes<W: BitWrite + ?Sized>(bs: &mut W, bytes: &[u8], buffer: &mut Vec<u8>) -> Result<()> {
+    if bytes.len() > 255 {
+        return Err(too_long());
+    }
+
+    let shared = bytes.iter()
+        .zip(buffer.iter())
+        .take_while(|(a, b)| a == b && **a != 0)
+        .count();
+    buffer.clear();
+    buffer.extend_from_slice(bytes);
+
+    if bs.write_flag(shared > 2)? {
+        bs.write_u8(shared as u8)?;
+        write_huffman_bytes(bs, &bytes[shared..])
+    } else {
+        write_huffman_bytes(bs, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_stream::{BitReader, BitWriter};
+
+    const MISSION: &[u8] = b"marble/data/missions/beginner/movement.mis";
+
+    // Written by a line for line port of Torque's HuffmanProcessor
+    // (buildTables, generateCodes, writeHuffBuffer) and BitStream::writeString
+    const MISSION_BYTES: [u8; 30] = [
+        0x55, 0x50, 0xDA, 0x6E, 0x7C, 0x34, 0x0F, 0x09, 0x19, 0xCD, 0x11, 0xB7, 0xC6, 0x4A, 0x2D,
+        0x9A, 0xEF, 0xBE, 0x67, 0xA2, 0xB7, 0x68, 0x8E, 0x54, 0x7A, 0xF4, 0x04, 0x25, 0xE2, 0x06,
+    ];
+    const HELLO_BYTES: [u8; 11] = [0x1B, 0x9C, 0xFE, 0x18, 0x1A, 0xED, 0x2B, 0xD4, 0x46, 0xA8, 0x02];
+    const BUFFERED_BYTES: [u8; 38] = [
+        0xAA, 0xA0, 0xB4, 0xDD, 0xF8, 0x68, 0x1E, 0x12, 0x32, 0x9A, 0x23, 0x6E, 0x8D, 0x95, 0x5A,
+        0x34, 0xDF, 0x7D, 0xCF, 0x44, 0x6F, 0xD1, 0x1C, 0xA9, 0xF4, 0xE8, 0x09, 0x4A, 0xC4, 0xAD,
+        0x47, 0x84, 0x85, 0x47, 0x75, 0x89, 0xB8, 0x01,
+    ];
+
+    fn huffman(bytes: &[u8]) -> Vec<u8> {
+        let mut bs = BitWriter::new();
+        write_huffman_bytes(&mut bs, bytes).unwrap();
+        bs.bytes()
+    }
+
+    #[test]
+    fn codes_match_torque() {
+        assert_eq!((TREE.leaves[b'e' as usize].code, TREE.leaves[b'e' as usize].bits), (15, 4));
+        assert_eq!((TREE.leaves[b' ' as usize].code, TREE.leaves[b' ' as usize].bits), (7, 4));
+        assert_eq!((TREE.leaves[b'z' as usize].code, TREE.leaves[b'z' as usize].bits), (145, 10));
+    }
+
+    #[test]
+    fn strings_match_torque() {
+        assert_eq!(huffman(MISSION), MISSION_BYTES);
+        assert_eq!(huffman(b"Hello, world!"), HELLO_BYTES);
+        // Nothing to compress, so a raw length of 0
+        assert_eq!(huffman(b""), [0x00, 0x00]);
+        // Codes for bytes Torque never expects are longer than 8 bits
+        assert_eq!(huffman(b"\xff\xfe\xfd"), [0x06, 0xFE, 0xFD, 0xFB, 0x01]);
+
+        assert_eq!(read_huffman_bytes(&mut BitReader::new(&MISSION_BYTES)).unwrap(), MISSION);
+        assert_eq!(read_huffman_bytes(&mut BitReader::new(&HELLO_BYTES)).unwrap(), b"Hello, world!");
+    }
+
+    #[test]
+    fn buffered_strings_match_torque() {
+        let jump = b"marble/data/missions/beginner/jump.mis";
+        let mut bs = BitWriter::new();
+        let mut buffer = vec![];
+        write_buffered_bytes(&mut bs, MISSION, &mut buffer).unwrap();
+        write_buffered_bytes(&mut bs, jump, &mut buffer).unwrap();
+        assert_eq!(bs.bytes(), BUFFERED_BYTES);
+
+        let mut bs = BitReader::new(&BUFFERED_BYTES);
+        let mut buffer = vec![];
+        assert_eq!(read_buffered_bytes(&mut bs, &mut buffer).unwrap(), MISSION);
+        assert_eq!(read_buffered_bytes(&mut bs, &mut buffer).unwrap(), jump);
+    }
+
+    #[test]
+    fn every_byte_round_trips() {
+        let all = (0..=255u8).collect::<Vec<_>>();
+        for chunk in all.chunks(17) {
+            let bytes = huffman(chunk);
+            assert_eq!(read_huffman_bytes(&mut BitReader::new(&bytes)).unwrap(), chunk);
+        }
+        let text = b"the quick brown fox jumps over the lazy dog";
+        let bytes = huffman(text);
+        assert_eq!(read_huffman_bytes(&mut BitReader::new(&bytes)).unwrap(), text);
+        // The length has to fit in a byte
+        assert!(write_huffman_bytes(&mut BitWriter::new(), &[b'a'; 255]).is_ok());
+        assert!(write_huffman_bytes(&mut BitWriter::new(), &[b'a'; 256]).is_err());
+    }
+}
@@ -1,24 +1,52 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 extern crate derive_more;
+#[cfg(feature = "tas")]
 extern crate nom;
+#[cfg(feature = "tas")]
 extern crate regex;
+#[cfg(feature = "wasm")]
 extern crate wasm_bindgen;
 extern crate cfg_if;
-#[macro_use]
-extern crate error_chain;
 extern crate serde;
+#[cfg(feature = "json")]
 extern crate serde_json;
+extern crate librec_derive;
+// So code generated by librec_derive can say ::librec:: inside this crate too
+extern crate self as librec;
 
 pub mod bit_stream;
+#[cfg(feature = "std")]
+pub mod bit_io;
+pub mod camera;
+pub mod codec;
+pub mod diff;
+pub mod dissect;
+pub mod edit;
+pub mod encoding;
+pub mod field;
+pub mod frame_reader;
+pub mod huffman;
+mod math;
+pub mod quantize;
+pub mod rec_format;
 pub mod recording;
+#[cfg(feature = "std")]
+pub mod recording_writer;
+pub mod salvage;
+pub mod stats;
+pub mod timeline;
+pub mod validate;
+#[cfg(feature = "tas")]
 pub mod tas_rec;
 pub mod error;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-use wasm_bindgen::prelude::*;
 use cfg_if::cfg_if;
-use crate::bit_stream::BitStream;
-use crate::recording::Recording;
-use crate::tas_rec::TasFile;
-use crate::error::Result;
+#[cfg(feature = "wasm")]
+pub use crate::wasm::{export_rec, import_rec, rec_camera, rec_stats, rec_timeline};
 
 cfg_if! {
     // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -30,53 +58,3 @@ cfg_if! {
     }
 }
 
-#[wasm_bindgen]
-extern {
-    fn alert(s: &str);
-}
-
-#[wasm_bindgen]
-pub fn import_rec(conts: Vec<u8>) -> Option<String> {
-    if let Ok(result) = import_opt(conts) {
-        Some(result)
-    } else {
-        None
-    }
-}
-
-fn import_opt(conts: Vec<u8>) -> Result<String> {
-    let mut bs = BitStream::new(conts);
-
-    let r = Recording::from_stream(&mut bs)?;
-    let tf = serde_json::to_string(&r)?;
-    Ok(tf)
-}
-
-#[wasm_bindgen]
-pub fn export_rec(input: String) -> Vec<u8> {
-    match export_opt(input) {
-        Ok(mut result) => {
-            result.insert(0, 1);
-            result
-        }
-        Err(error) => {
-            let mut result = format!("{:?}", error).into_bytes();
-            result.insert(0, 0);
-            result
-        }
-    }
-}
-
-fn export_opt(input: String) -> Result<Vec<u8>> {
-    let r = if let Ok(r) = serde_json::from_str::<Recording>(&input) {
-        r
-    } else {
-        let tf = TasFile::parse(input)?;
-        tf.into_rec()
-    };
-
-    let mut os = BitStream::new(vec![]);
-    r.into_stream(&mut os)?;
-    Ok(os.bytes())
-}
-
@@ -0,0 +1,44 @@
+// Float functions that live on f64 with std but need libm without it. With
+// std the inherent methods are used so the results stay bit-for-bit the same.
+
+#[cfg(feature = "std")]
+mod imp {
+    pub fn round(x: f64) -> f64 {
+        x.round()
+    }
+
+    pub fn floor(x: f64) -> f64 {
+        x.floor()
+    }
+
+    pub fn trunc(x: f64) -> f64 {
+        x.trunc()
+    }
+
+    pub fn exp2(x: f64) -> f64 {
+        x.exp2()
+    }
+
+    pub fn sin(x: f64) -> f64 {
+        x.sin()
+    }
+
+    pub fn cos(x: f64) -> f64 {
+        x.cos()
+    }
+
+    pub fn atan2(y: f64, x: f64) -> f64 {
+        y.atan2(x)
+    }
+
+    pub fn sqrt(x: f64) -> f64 {
+        x.sqrt()
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    pub use libm::{atan2, cos, exp2, floor, round, sin, sqrt, trunc};
+}
+
+pub use self::imp::*;
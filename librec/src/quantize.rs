@@ -0,0 +1,95 @@
+use alloc::format;
+use crate::error::Result;
+use crate::math;
+use crate::error::ErrorKind::{GenericError, GenericError2};
+use serde::{Serialize, Deserialize};
+
+// Fraction of a step a value can be off its grid and still count as on it
+const ON_GRID: f64 = 1e-6;
+
+// How a float gets snapped onto its integer grid. Truncate is what the old
+// `as u64` cast did, so it's the default and recs come out the same as they
+// always have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Rounding {
+    #[default]
+    Truncate,
+    Nearest,
+    Floor,
+}
+
+// What to do with values that do not fit in the bits after rounding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OutOfRange {
+    #[default]
+    Error,
+    Saturate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Quantization {
+    pub rounding: Rounding,
+    pub out_of_range: OutOfRange,
+}
+
+// The integer that actually gets written, and how far the value it decodes
+// back to is from the one that was asked for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantized {
+    pub raw: u64,
+    pub error: f64,
+}
+
+impl Quantization {
+    pub fn new(rounding: Rounding, out_of_range: OutOfRange) -> Quantization {
+        Quantization {
+            rounding,
+            out_of_range,
+        }
+    }
+
+    // Number of `scale` sized steps `value` is away from `offset`, rounded but
+    // not range checked yet
+    pub fn steps(&self, value: f64, scale: f64, offset: f64) -> Result<f64> {
+        if !value.is_finite() {
+            return Err(GenericError("Cannot quantize a value that is not finite").into());
+        }
+        let scaled = (value - offset) / scale;
+        // Values already on the grid, give or take float noise, keep their
+        // step whatever the rounding. Decoding and encoding again has to come
+        // back to the same integer.
+        let nearest = math::round(scaled);
+        if (scaled - nearest).abs() < ON_GRID {
+            return Ok(nearest);
+        }
+        Ok(match self.rounding {
+            Rounding::Truncate => math::trunc(scaled),
+            Rounding::Nearest => math::round(scaled),
+            Rounding::Floor => math::floor(scaled),
+        })
+    }
+
+    // Fit rounded steps into `bits` bits
+    pub fn fit(&self, steps: f64, bits: u8) -> Result<u64> {
+        let max = math::exp2(f64::from(bits)) - 1f64;
+        if steps < 0f64 || steps > max {
+            match self.out_of_range {
+                OutOfRange::Error => {
+                    return Err(GenericError2(format!("Value {} steps does not fit in {} bits", steps, bits)).into());
+                }
+                OutOfRange::Saturate => {
+                    return Ok(if steps < 0f64 { 0 } else { max as u64 });
+                }
+            }
+        }
+        Ok(steps as u64)
+    }
+
+    pub fn quantize(&self, value: f64, bits: u8, scale: f64, offset: f64) -> Result<Quantized> {
+        let raw = self.fit(self.steps(value, scale, offset)?, bits)?;
+        Ok(Quantized {
+            raw,
+            error: raw as f64 * scale + offset - value,
+        })
+    }
+}
@@ -0,0 +1,237 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::Read;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use crate::bit_io::IoBitReader;
+use crate::bit_stream::{BitRead, BitReader, BitTake, BitWriter};
+use crate::error::Result;
+use crate::error::ErrorKind::{GenericError, GenericError2};
+use crate::recording::{Frame, ReadOptions, Recording, WriteOptions};
+
+// Whatever a format's header says about a rec, beyond the mission and frames
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    // Name of the RecFormat that read it, and that writes it back
+    pub format: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    // Anything else in the header, in the order it was in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<(String, String)>,
+}
+
+// One game's rec layout. Formats with a header put it in
+// `Recording::metadata` on the way in and read it back from there on the way
+// out, so a rec keeps its format through a round trip.
+pub trait RecFormat: Sync {
+    // Short name, used on the command line and in `Metadata::format`
+    fn name(&self) -> &'static str;
+
+    // How sure this format is that a rec starting with `prefix` is one of its
+    // recs: 0 for not at all, 1 for it starts like one, 2 for it looks right
+    // and 3 for a magic number or something else that can't be a
+    // coincidence. `prefix` is the first SNIFF_BYTES of the rec, or all of it
+    // if it's shorter, so sniffing never has to read a whole rec.
+    fn sniff(&self, prefix: &[u8]) -> u8;
+
+    fn read(&self, data: &[u8], options: &ReadOptions) -> Result<Recording>;
+
+    // Same as `read`, a bit at a time straight out of `reader`
+    #[cfg(feature = "std")]
+    fn read_from(&self, reader: &mut dyn Read, options: &ReadOptions) -> Result<Recording>;
+
+    fn write(&self, recording: Recording, options: &WriteOptions) -> Result<Vec<u8>>;
+}
+
+// Enough for a whole mission string and the block after it, or any of the
+// headers
+pub const SNIFF_BYTES: usize = 1024;
+
+// Marble Blast Gold: the mission string and then length prefixed frame
+// blocks, no header
+pub struct Gold;
+
+impl RecFormat for Gold {
+    fn name(&self) -> &'static str {
+        "gold"
+    }
+
+    // Only the mission string and the first block, which have to decode
+    fn sniff(&self, prefix: &[u8]) -> u8 {
+        let mut bs = BitReader::new(prefix);
+        let mission = match bs.read_string_bytes() {
+            Ok(mission) => mission,
+            Err(_) => return 0,
+        };
+        let first_block = match bs.read_u8() {
+            Ok(0) => true,
+            Ok(length) => Frame::from_stream(&mut BitTake::new(&mut bs, length as usize * 8)).is_ok(),
+            // Nothing but a mission, which is still a rec
+            Err(_) => true,
+        };
+        match first_block {
+            true if mission.to_ascii_lowercase().ends_with(b".mis") => 2,
+            true => 1,
+            false => 0,
+        }
+    }
+
+    fn read(&self, data: &[u8], options: &ReadOptions) -> Result<Recording> {
+        Recording::from_bytes_with(data, options)
+    }
+
+    #[cfg(feature = "std")]
+    fn read_from(&self, reader: &mut dyn Read, options: &ReadOptions) -> Result<Recording> {
+        Recording::from_stream_with(&mut IoBitReader::from_reader(reader), options)
+    }
+
+    fn write(&self, recording: Recording, options: &WriteOptions) -> Result<Vec<u8>> {
+        let mut bs = BitWriter::new();
+        recording.into_stream_with(&mut bs, options)?;
+        Ok(bs.bytes())
+    }
+}
+
+// Every format librec knows, checked in order when detecting. New variants
+// go here, each one checked against real recs from its game.
+static FORMATS: [&dyn RecFormat; 1] = [&Gold];
+
+pub fn formats() -> &'static [&'static dyn RecFormat] {
+    &FORMATS
+}
+
+pub fn find_format(name: &str) -> Option<&'static dyn RecFormat> {
+    FORMATS.iter().copied().find(|format| format.name() == name)
+}
+
+// The format most sure about a rec starting with `prefix`, the first one
+// listed on a tie. Anything past SNIFF_BYTES is ignored.
+pub fn detect(prefix: &[u8]) -> Option<&'static dyn RecFormat> {
+    let prefix = &prefix[..prefix.len().min(SNIFF_BYTES)];
+    let mut best: Option<(&'static dyn RecFormat, u8)> = None;
+    for &format in FORMATS.iter() {
+        let score = format.sniff(prefix);
+        if score > 0 && best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((format, score));
+        }
+    }
+    best.map(|(format, _)| format)
+}
+
+// Read a rec of whatever format it turns out to be
+pub fn read_any(data: &[u8], options: &ReadOptions) -> Result<Recording> {
+    match detect(data) {
+        Some(format) => format.read(data, options),
+        None => Err(GenericError("Not a rec in any known format").into()),
+    }
+}
+
+// read_any straight out of `reader`, only the first SNIFF_BYTES get buffered
+// up to detect the format with. Failures of the reader itself are `is_io()`.
+#[cfg(feature = "std")]
+pub fn read_any_from<R: Read>(mut reader: R, options: &ReadOptions) -> Result<Recording> {
+    let mut prefix = Vec::with_capacity(SNIFF_BYTES);
+    (&mut reader).take(SNIFF_BYTES as u64).read_to_end(&mut prefix)?;
+    match detect(&prefix) {
+        Some(format) => format.read_from(&mut prefix.as_slice().chain(reader), options),
+        None => Err(GenericError("Not a rec in any known format").into()),
+    }
+}
+
+// Write a rec back in the format it was read from, Gold if it doesn't say
+pub fn write_any(recording: Recording, options: &WriteOptions) -> Result<Vec<u8>> {
+    let format = match &recording.metadata {
+        Some(metadata) => find_format(&metadata.format)
+            .ok_or_else(|| GenericError2(format!("Unknown rec format {:?}", metadata.format)))?,
+        None => &Gold,
+    };
+    format.write(recording, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::Encoding;
+    use crate::recording::{Angle, Axis, Move};
+
+    fn rec() -> Recording {
+        let mv = Move {
+            yaw: Some(Angle(5215)),
+            pitch: None,
+            roll: None,
+            mx: Axis(20),
+            my: Axis(0),
+            mz: Axis(16),
+            freelook: false,
+            triggers: Default::default(),
+        };
+        Recording {
+            mission: "marble/data/missions/beginner/movement.mis".to_string(),
+            frames: (0..300).map(|i| Frame { moves: [Some(mv), None], delta: 16 + i % 3 }).collect(),
+            mission_encoding: Encoding::Utf8,
+            framing: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn every_format_round_trips_and_is_detected() {
+        for &format in formats() {
+            let recording = rec();
+            let bytes = format.write(recording.clone(), &WriteOptions::default()).unwrap();
+            assert_eq!(bytes, write_any(recording.clone(), &WriteOptions::default()).unwrap());
+            assert_eq!(detect(&bytes).map(|format| format.name()), Some(format.name()));
+            let back = read_any(&bytes, &ReadOptions::default()).unwrap();
+            assert_eq!(back.metadata, recording.metadata);
+            assert_eq!(back.frames.len(), recording.frames.len());
+            assert_eq!(write_any(back, &WriteOptions::default()).unwrap(), bytes);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn streaming_reads_the_same() {
+        for &format in formats() {
+            let bytes = format.write(rec(), &WriteOptions::default()).unwrap();
+            let streamed = read_any_from(bytes.as_slice(), &ReadOptions::default()).unwrap();
+            let read = read_any(&bytes, &ReadOptions::default()).unwrap();
+            assert_eq!(streamed.metadata, read.metadata);
+            assert_eq!(streamed.frames.len(), read.frames.len());
+        }
+    }
+
+    // Sniffing only looks at the start, so a rec damaged further in is
+    // still detected and only fails when it's read
+    #[test]
+    fn gold_sniffs_the_prefix() {
+        let mut bytes = rec().into_bytes().unwrap();
+        assert!(bytes.len() > SNIFF_BYTES);
+        assert_eq!(Gold.sniff(&bytes[..SNIFF_BYTES]), 2);
+        let end = bytes.len() - 3;
+        bytes[end] = 0xFF;
+        assert_eq!(detect(&bytes).map(|format| format.name()), Some("gold"));
+        assert_eq!(Gold.sniff(b"\x05junk"), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reader_errors_are_io() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+        }
+        let error = read_any_from(Broken, &ReadOptions::default()).unwrap_err();
+        assert!(error.is_io());
+
+        let bytes = rec().into_bytes().unwrap();
+        let error = read_any_from(bytes.as_slice().chain(Broken), &ReadOptions::default()).unwrap_err();
+        assert!(error.is_io());
+    }
+}
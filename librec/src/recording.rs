@@ -1,11 +1,54 @@
-use crate::bit_stream::BitStream;
-use std::cmp::max;
-use std::f64::consts::PI;
+use crate::bit_stream::{BitRead, BitReader, BitTake, BitWrite, BitWriter};
+use crate::codec::BitCodec;
+use crate::field::{FieldValue, ToFieldValue};
+use crate::encoding::Encoding;
+use crate::error::Error;
+use crate::error::ErrorKind::GenericError2;
+#[cfg(feature = "std")]
+use crate::bit_io::{IoBitReader, IoBitWriter};
+use crate::huffman::StringCodec;
+use crate::quantize::Quantization;
+use crate::rec_format::Metadata;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::vec;
+use core::cmp::{max, min};
+use core::convert::TryFrom;
+use core::fmt;
+use core::mem;
+use core::ops::{BitOr, BitOrAssign};
+use core::f64::consts::PI;
+use core::result::Result as StdResult;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
 use crate::error::Result;
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// A move exactly as it is in a rec. The integers are what gets written, the
+// floats they stand for come from yaw() and the other accessors, and
+// MoveValues goes the other way. In JSON it's the floats with the integers
+// alongside, see MoveJson.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, BitCodec)]
+#[serde(into = "MoveJson", try_from = "MoveJson")]
 pub struct Move {
+    #[optional]
+    pub yaw: Option<Angle>,
+    #[optional]
+    pub pitch: Option<Angle>,
+    #[optional]
+    pub roll: Option<Angle>,
+    pub mx: Axis,
+    pub my: Axis,
+    pub mz: Axis,
+    pub freelook: bool,
+    pub triggers: Triggers,
+}
+
+// A move with its angles and axes as floats, like TAS files have them.
+// Quantizing it gives the Move that gets written.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MoveValues {
     pub yaw: Option<f64>,
     pub pitch: Option<f64>,
     pub roll: Option<f64>,
@@ -13,12 +56,70 @@ pub struct Move {
     pub my: f64,
     pub mz: f64,
     pub freelook: bool,
-    pub triggers: [bool; 6],
+    pub triggers: Triggers,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// A Move in JSON: the floats, so it can be read and edited, and the integers
+// they were decoded from. Those win for as long as the floats still decode
+// from them, so rec -> JSON -> rec never moves a step. Editing a float wins,
+// as does leaving the integers out.
+#[derive(Serialize, Deserialize)]
+struct MoveJson {
+    #[serde(flatten)]
+    values: MoveValues,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    raw: Option<MoveSteps>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct MoveSteps {
+    yaw: Option<Angle>,
+    pitch: Option<Angle>,
+    roll: Option<Angle>,
+    mx: Axis,
+    my: Axis,
+    mz: Axis,
+}
+
+// Yaw, pitch or roll as written: 2^16 steps around the circle, starting at 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Angle(pub u16);
+
+// mx, my or mz as written: 6 bits of 1/16 steps up from -1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Axis(pub u8);
+
+// Torque's six move triggers, by what Marble Blast uses them for. Gold only
+// looks at fire (use the powerup) and jump, the rest are still recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Trigger {
+    Fire,
+    AltFire,
+    Jump,
+    Trigger3,
+    Trigger4,
+    Trigger5,
+}
+
+// Set of held triggers, a bit per trigger in Trigger order. In JSON it's the
+// same six bools it always was.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "[bool; 6]", into = "[bool; 6]")]
+pub struct Triggers(u8);
+
+// Which of a frame's two moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MoveSlot {
+    First,
+    Second,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, BitCodec)]
 pub struct Frame {
+    #[optional]
+    #[codec(element = "move")]
     pub moves: [Option<Move>; 2],
+    #[bits(10)]
     pub delta: u16,
 }
 
@@ -26,135 +127,1134 @@ pub struct Frame {
 pub struct Recording {
     pub mission: String,
     pub frames: Vec<Frame>,
+    // Code page the mission was stored in, so it is written back the same way
+    #[serde(default, skip_serializing_if = "Encoding::is_utf8")]
+    pub mission_encoding: Encoding,
+    // Block layout from a lossless read, written back as it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub framing: Option<Framing>,
+    // Header of formats that have one, see rec_format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+}
+
+// Bits that aren't part of any frame, packed LSB first like the rec itself
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawBits {
+    pub bytes: Vec<u8>,
+    pub len_bits: usize,
+}
+
+// Everything about a rec's layout that the frames themselves don't say.
+// Only lossless reads fill this in, and with it a rec is written back byte
+// for byte the same as it was read.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Framing {
+    // Bits after each frame up to the end of its block, one per frame
+    pub padding: Vec<RawBits>,
+    // Whether the blocks ended with a zero length block
+    pub terminated: bool,
+    // Whatever came after the last block, including a block cut short
+    pub trailing: RawBits,
+}
+
+// How far each written field ends up from what was in the Move, 0 for fields
+// that were not written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MoveError {
+    pub yaw: f64,
+    pub pitch: f64,
+    pub roll: f64,
+    pub mx: f64,
+    pub my: f64,
+    pub mz: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions {
+    pub codec: StringCodec,
+    // Keep the block layout in `Recording::framing`
+    pub lossless: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    pub codec: StringCodec,
+    pub quantization: Quantization,
+}
+
+// The game runs a tick, and so makes a move, every 32 ms
+pub const TICK_MS: u16 = 32;
+
+// Torque scales angles from [-pi, pi] -> [0, 2^16]
+const ANGLE_SCALE: f64 = PI / 32768f64;
+
+impl MoveError {
+    // Anything smaller than this is float noise, not a real step off
+    pub const TOLERANCE: f64 = 1e-9;
+
+    pub fn max_abs(&self) -> f64 {
+        [self.yaw, self.pitch, self.roll, self.mx, self.my, self.mz]
+            .iter()
+            .fold(0f64, |acc, e| acc.max(e.abs()))
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.max_abs() < MoveError::TOLERANCE
+    }
+}
+
+impl Trigger {
+    pub const ALL: [Trigger; 6] = [
+        Trigger::Fire,
+        Trigger::AltFire,
+        Trigger::Jump,
+        Trigger::Trigger3,
+        Trigger::Trigger4,
+        Trigger::Trigger5,
+    ];
+
+    // Torque's trigger number, $mvTriggerCount0 and so on
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_index(index: usize) -> Option<Trigger> {
+        Trigger::ALL.get(index).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Trigger::Fire => "fire",
+            Trigger::AltFire => "alt_fire",
+            Trigger::Jump => "jump",
+            Trigger::Trigger3 => "trigger3",
+            Trigger::Trigger4 => "trigger4",
+            Trigger::Trigger5 => "trigger5",
+        }
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Triggers {
+    pub const fn empty() -> Triggers {
+        Triggers(0)
+    }
+
+    pub const fn all() -> Triggers {
+        Triggers(0b11_1111)
+    }
+
+    // Bits past the sixth are dropped
+    pub const fn from_bits(bits: u8) -> Triggers {
+        Triggers(bits & Triggers::all().0)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn pressed(self, trigger: Trigger) -> bool {
+        self.0 & (1 << trigger.index()) != 0
+    }
+
+    pub fn press(&mut self, trigger: Trigger) {
+        self.set(trigger, true);
+    }
+
+    pub fn release(&mut self, trigger: Trigger) {
+        self.set(trigger, false);
+    }
+
+    pub fn set(&mut self, trigger: Trigger, held: bool) {
+        if held {
+            self.0 |= 1 << trigger.index();
+        } else {
+            self.0 &= !(1 << trigger.index());
+        }
+    }
+
+    // The held triggers, in order
+    pub fn iter(self) -> impl Iterator<Item = Trigger> {
+        Trigger::ALL.iter().copied().filter(move |&trigger| self.pressed(trigger))
+    }
+
+    pub fn to_array(self) -> [bool; 6] {
+        let mut held = [false; 6];
+        for trigger in self.iter() {
+            held[trigger.index()] = true;
+        }
+        held
+    }
+}
+
+impl From<[bool; 6]> for Triggers {
+    fn from(held: [bool; 6]) -> Triggers {
+        let mut triggers = Triggers::empty();
+        for (&trigger, &held) in Trigger::ALL.iter().zip(held.iter()) {
+            triggers.set(trigger, held);
+        }
+        triggers
+    }
+}
+
+impl From<Triggers> for [bool; 6] {
+    fn from(triggers: Triggers) -> [bool; 6] {
+        triggers.to_array()
+    }
+}
+
+impl From<Trigger> for Triggers {
+    fn from(trigger: Trigger) -> Triggers {
+        let mut triggers = Triggers::empty();
+        triggers.press(trigger);
+        triggers
+    }
+}
+
+impl<T: Into<Triggers>> BitOr<T> for Triggers {
+    type Output = Triggers;
+
+    fn bitor(self, other: T) -> Triggers {
+        Triggers(self.0 | other.into().0)
+    }
+}
+
+impl<T: Into<Triggers>> BitOr<T> for Trigger {
+    type Output = Triggers;
+
+    fn bitor(self, other: T) -> Triggers {
+        Triggers::from(self) | other
+    }
+}
+
+impl<T: Into<Triggers>> BitOrAssign<T> for Triggers {
+    fn bitor_assign(&mut self, other: T) {
+        self.0 |= other.into().0;
+    }
+}
+
+impl fmt::Debug for Triggers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+// A bool per trigger, each its own field like the rest of a move
+impl BitCodec for Triggers {
+    fn read_from<R: BitRead>(bs: &mut R) -> Result<Triggers> {
+        let mut triggers = Triggers::empty();
+        for &trigger in Trigger::ALL.iter() {
+            triggers.set(trigger, bs.read_field("trigger", |bs| bs.read_bool())?);
+        }
+        Ok(triggers)
+    }
+
+    fn write_to<W: BitWrite>(&self, bs: &mut W, _policy: Quantization) -> Result<()> {
+        for &trigger in Trigger::ALL.iter() {
+            bs.write_field("trigger", |bs| bs.write_bool(self.pressed(trigger)).map(|_| 0f64))?;
+        }
+        Ok(())
+    }
+}
+
+impl ToFieldValue for Triggers {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Empty
+    }
+}
+
+impl MoveSlot {
+    pub const ALL: [MoveSlot; 2] = [MoveSlot::First, MoveSlot::Second];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Angle {
+    const BITS: u8 = 16;
+
+    // In [-pi, pi) like the game hands them out
+    pub fn to_f64(self) -> f64 {
+        let angle = f64::from(self.0) * ANGLE_SCALE;
+        if angle >= PI {
+            angle - 2f64 * PI
+        } else {
+            angle
+        }
+    }
+
+    // The step `angle` gets written as, wrapped into [0, 2pi) first, and how
+    // far it is from `angle`
+    pub fn from_f64(angle: f64, policy: Quantization) -> Result<(Angle, f64)> {
+        let angle = if angle < 0f64 { angle + 2f64 * PI } else { angle };
+        let steps = policy.steps(angle, ANGLE_SCALE, 0f64)?;
+        // Rounding up from just under 2pi gives 2^16, which is the same angle as 0
+        if steps == 65536f64 {
+            return Ok((Angle(0), 2f64 * PI - angle));
+        }
+        let raw = policy.fit(steps, Angle::BITS)?;
+        Ok((Angle(raw as u16), raw as f64 * ANGLE_SCALE - angle))
+    }
+}
+
+impl Axis {
+    pub const BITS: u8 = 6;
+    pub const SCALE: f64 = 1f64 / 16f64;
+    pub const OFFSET: f64 = -1f64;
+    // What the lowest and highest steps decode to. The game's input only
+    // goes from -1 to 1, the rest of the range is never recorded.
+    pub const MIN: f64 = Axis::OFFSET;
+    pub const MAX: f64 = Axis::OFFSET + Axis::SCALE * ((1 << Axis::BITS) - 1) as f64;
+
+    pub fn to_f64(self) -> f64 {
+        f64::from(self.0) * Axis::SCALE + Axis::OFFSET
+    }
+
+    // The step `value` gets written as, and how far it is from `value`
+    pub fn from_f64(value: f64, policy: Quantization) -> Result<(Axis, f64)> {
+        let quantized = policy.quantize(value, Axis::BITS, Axis::SCALE, Axis::OFFSET)?;
+        Ok((Axis(quantized.raw as u8), quantized.error))
+    }
+}
+
+impl BitCodec for Angle {
+    fn read_from<R: BitRead>(bs: &mut R) -> Result<Angle> {
+        bs.read_bits_u16(Angle::BITS).map(Angle)
+    }
+
+    fn write_to<W: BitWrite>(&self, bs: &mut W, _policy: Quantization) -> Result<()> {
+        bs.write_bits_u16(self.0, Angle::BITS)
+    }
+}
+
+impl BitCodec for Axis {
+    fn read_from<R: BitRead>(bs: &mut R) -> Result<Axis> {
+        bs.read_bits_u8(Axis::BITS).map(Axis)
+    }
+
+    fn write_to<W: BitWrite>(&self, bs: &mut W, _policy: Quantization) -> Result<()> {
+        // Only the low bits would get written, and a raw move from JSON can
+        // hold anything
+        if self.0 >> Axis::BITS != 0 {
+            return Err(GenericError2(format!("Axis step {} does not fit in {} bits", self.0, Axis::BITS)).into());
+        }
+        bs.write_bits_u8(self.0, Axis::BITS)
+    }
+}
+
+// Dissections show what the integers decode to
+impl ToFieldValue for Angle {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Float(self.to_f64())
+    }
+}
+
+impl ToFieldValue for Axis {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Float(self.to_f64())
+    }
 }
 
 impl Move {
-    pub fn from_stream(bs: &mut BitStream) -> Result<Move> {
-        let yaw = bs.read_optional(|bs| Move::read_angle(bs))?;
-        let pitch = bs.read_optional(|bs| Move::read_angle(bs))?;
-        let roll = bs.read_optional(|bs| Move::read_angle(bs))?;
-        let mx = bs.read_scaled_f64_bits(6, 1f64 / 16f64, -1.0f64)?;
-        let my = bs.read_scaled_f64_bits(6, 1f64 / 16f64, -1.0f64)?;
-        let mz = bs.read_scaled_f64_bits(6, 1f64 / 16f64, -1.0f64)?;
-        let freelook = bs.read_bool()?;
-        let mut triggers = [false; 6];
-        for i in 0..6 {
-            triggers[i] = bs.read_bool()?;
-        }
-        Ok(Move {
+    pub fn from_stream<R: BitRead>(bs: &mut R) -> Result<Move> {
+        Move::read_from(bs)
+    }
+
+    pub fn into_stream<W: BitWrite>(self, bs: &mut W) -> Result<()> {
+        self.write_to(bs, Quantization::default())
+    }
+
+    pub fn yaw(&self) -> Option<f64> {
+        self.yaw.map(Angle::to_f64)
+    }
+
+    pub fn pitch(&self) -> Option<f64> {
+        self.pitch.map(Angle::to_f64)
+    }
+
+    pub fn roll(&self) -> Option<f64> {
+        self.roll.map(Angle::to_f64)
+    }
+
+    pub fn mx(&self) -> f64 {
+        self.mx.to_f64()
+    }
+
+    pub fn my(&self) -> f64 {
+        self.my.to_f64()
+    }
+
+    pub fn mz(&self) -> f64 {
+        self.mz.to_f64()
+    }
+
+    pub fn values(&self) -> MoveValues {
+        MoveValues::from(*self)
+    }
+
+    fn steps(&self) -> MoveSteps {
+        MoveSteps {
+            yaw: self.yaw,
+            pitch: self.pitch,
+            roll: self.roll,
+            mx: self.mx,
+            my: self.my,
+            mz: self.mz,
+        }
+    }
+}
+
+impl From<Move> for MoveValues {
+    fn from(mv: Move) -> MoveValues {
+        MoveValues {
+            yaw: mv.yaw(),
+            pitch: mv.pitch(),
+            roll: mv.roll(),
+            mx: mv.mx(),
+            my: mv.my(),
+            mz: mv.mz(),
+            freelook: mv.freelook,
+            triggers: mv.triggers,
+        }
+    }
+}
+
+impl MoveValues {
+    // The move these get written as, and how far each field ends up from
+    // what was asked for
+    pub fn quantize(&self, policy: Quantization) -> Result<(Move, MoveError)> {
+        let mut error = MoveError::default();
+        let angle = |angle: Option<f64>, error: &mut f64| -> Result<Option<Angle>> {
+            angle
+                .map(|angle| {
+                    let (raw, angle_error) = Angle::from_f64(angle, policy)?;
+                    *error = angle_error;
+                    Ok(raw)
+                })
+                .transpose()
+        };
+        let yaw = angle(self.yaw, &mut error.yaw)?;
+        let pitch = angle(self.pitch, &mut error.pitch)?;
+        let roll = angle(self.roll, &mut error.roll)?;
+        let (mx, mx_error) = Axis::from_f64(self.mx, policy)?;
+        let (my, my_error) = Axis::from_f64(self.my, policy)?;
+        let (mz, mz_error) = Axis::from_f64(self.mz, policy)?;
+        error.mx = mx_error;
+        error.my = my_error;
+        error.mz = mz_error;
+
+        let mv = Move {
             yaw,
             pitch,
             roll,
             mx,
             my,
             mz,
-            freelook,
-            triggers,
-        })
+            freelook: self.freelook,
+            triggers: self.triggers,
+        };
+        Ok((mv, error))
     }
 
-    pub fn into_stream(self, bs: &mut BitStream) -> Result<()> {
-        bs.write_optional(self.yaw, |bs, angle| Move::write_angle(bs, angle))?;
-        bs.write_optional(self.pitch, |bs, angle| Move::write_angle(bs, angle))?;
-        bs.write_optional(self.roll, |bs, angle| Move::write_angle(bs, angle))?;
-        bs.write_scaled_f64_bits(self.mx, 6, 1f64 / 16f64, -1.0f64)?;
-        bs.write_scaled_f64_bits(self.my, 6, 1f64 / 16f64, -1.0f64)?;
-        bs.write_scaled_f64_bits(self.mz, 6, 1f64 / 16f64, -1.0f64)?;
-        bs.write_bool(self.freelook)?;
-        for i in 0..6 {
-            bs.write_bool(self.triggers[i])?;
+    // The same move again if these still decode from its integers
+    fn decodes_to(&self, steps: MoveSteps) -> Option<Move> {
+        let angle = |value: Option<f64>, raw: Option<Angle>| match (value, raw) {
+            (Some(value), Some(raw)) => (value - raw.to_f64()).abs() < MoveError::TOLERANCE,
+            (value, raw) => value.is_none() && raw.is_none(),
+        };
+        let axis = |value: f64, raw: Axis| (value - raw.to_f64()).abs() < MoveError::TOLERANCE;
+        let decodes = angle(self.yaw, steps.yaw)
+            && angle(self.pitch, steps.pitch)
+            && angle(self.roll, steps.roll)
+            && axis(self.mx, steps.mx)
+            && axis(self.my, steps.my)
+            && axis(self.mz, steps.mz);
+        if !decodes {
+            return None;
         }
-        Ok(())
+        Some(Move {
+            yaw: steps.yaw,
+            pitch: steps.pitch,
+            roll: steps.roll,
+            mx: steps.mx,
+            my: steps.my,
+            mz: steps.mz,
+            freelook: self.freelook,
+            triggers: self.triggers,
+        })
     }
+}
 
-    fn read_angle(bs: &mut BitStream) -> Result<f64> {
-        // Torque scales these from [-pi, pi] -> [0, 2^16]
-        let angle = bs.read_scaled_f64_bits(16, PI / 32768f64, 0f64)?;
-        if angle >= PI {
-            Ok(angle - 2f64 * PI)
-        } else {
-            Ok(angle)
+impl From<Move> for MoveJson {
+    fn from(mv: Move) -> MoveJson {
+        MoveJson {
+            values: mv.values(),
+            raw: Some(mv.steps()),
         }
     }
+}
 
-    fn write_angle(bs: &mut BitStream, mut angle: f64) -> Result<()> {
-        // Torque scales these from [-pi, pi] -> [0, 2^16]
-        if angle < 0f64 {
-            angle += 2f64 * PI;
+impl TryFrom<MoveJson> for Move {
+    type Error = Error;
+
+    fn try_from(json: MoveJson) -> Result<Move> {
+        match json.raw.and_then(|steps| json.values.decodes_to(steps)) {
+            Some(mv) => Ok(mv),
+            None => json.values.quantize(Quantization::default()).map(|(mv, _)| mv),
         }
-        bs.write_scaled_f64_bits(angle, 16, PI / 32768f64, 0f64)
     }
 }
 
 impl Frame {
-    pub fn from_stream(bs: &mut BitStream) -> Result<Frame> {
-        let move0 = bs.read_optional(|bs| Move::from_stream(bs))?;
-        let move1 = bs.read_optional(|bs| Move::from_stream(bs))?;
-        let delta = bs.read_bits_u16(10)?;
-        Ok(Frame {
-            moves: [move0, move1],
-            delta,
-        })
+    // Longest delta the 10 bit field can hold, in ms
+    pub const MAX_DELTA: u16 = (1 << 10) - 1;
+
+    pub fn from_stream<R: BitRead>(bs: &mut R) -> Result<Frame> {
+        Frame::read_from(bs)
     }
 
-    pub fn into_stream(self, bs: &mut BitStream) -> Result<()> {
-        let [m1, m2] = self.moves;
-        bs.write_optional(m1, |bs, mv| mv.into_stream(bs))?;
-        bs.write_optional(m2, |bs, mv| mv.into_stream(bs))?;
-        bs.write_bits_u16(self.delta, 10)
+    pub fn into_stream<W: BitWrite>(self, bs: &mut W) -> Result<()> {
+        self.write_to(bs, Quantization::default())
     }
 
     pub fn has_move(&self) -> bool {
         self.moves[0].is_some() || self.moves[1].is_some()
     }
+
+    pub fn get_move(&self, slot: MoveSlot) -> Option<&Move> {
+        self.moves[slot.index()].as_ref()
+    }
+
+    pub fn get_move_mut(&mut self, slot: MoveSlot) -> Option<&mut Move> {
+        self.moves[slot.index()].as_mut()
+    }
+
+    pub fn set_move(&mut self, slot: MoveSlot, mv: Option<Move>) {
+        self.moves[slot.index()] = mv;
+    }
+
+    pub fn first_move(&self) -> Option<&Move> {
+        self.get_move(MoveSlot::First)
+    }
+
+    pub fn second_move(&self) -> Option<&Move> {
+        self.get_move(MoveSlot::Second)
+    }
+}
+
+// Only around while a block is read, boxing the frame would cost an
+// allocation for every one
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Block {
+    Frame(Frame, RawBits),
+    End,
+    Truncated,
+}
+
+impl ToFieldValue for Block {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Empty
+    }
+}
+
+impl ToFieldValue for RawBits {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Empty
+    }
+}
+
+impl RawBits {
+    pub fn is_empty(&self) -> bool {
+        self.len_bits == 0
+    }
+
+    // Zeroes from the end of the frame to the end of a block the size the
+    // game would make it, None if the frame can't be written
+    pub(crate) fn game_padding(frame: &Frame) -> Option<RawBits> {
+        let mut bs = BitWriter::new();
+        frame.write_to(&mut bs, Quantization::default()).ok()?;
+        let frame_bits = bs.len_bits();
+        let len_bits = max(frame_bits.div_ceil(8), 4) * 8 - frame_bits;
+        Some(RawBits {
+            bytes: vec![0; len_bits.div_ceil(8)],
+            len_bits,
+        })
+    }
+
+    pub(crate) fn read<R: BitRead + ?Sized>(bs: &mut R, bits: usize) -> Result<RawBits> {
+        let mut out = BitWriter::new();
+        let mut left = bits;
+        while left > 0 {
+            let chunk = min(left, 64) as u8;
+            out.write_bits(bs.read_bits(chunk)?, chunk)?;
+            left -= chunk as usize;
+        }
+        Ok(RawBits::from(out))
+    }
+
+    fn write<W: BitWrite + ?Sized>(&self, bs: &mut W) -> Result<()> {
+        write_raw(bs, &self.bytes, self.len_bits)
+    }
+}
+
+impl From<BitWriter> for RawBits {
+    fn from(bits: BitWriter) -> RawBits {
+        RawBits {
+            len_bits: bits.len_bits(),
+            bytes: bits.bytes(),
+        }
+    }
+}
+
+// Copy the first `bits` bits of `bytes` a word at a time
+fn write_raw<W: BitWrite + ?Sized>(bs: &mut W, bytes: &[u8], bits: usize) -> Result<()> {
+    let mut left = bits;
+    for chunk in bytes.chunks(8) {
+        if left == 0 {
+            break;
+        }
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u64::from_le_bytes(word);
+        let bits = min(left, chunk.len() * 8);
+        if bits < 64 {
+            value &= (1u64 << bits) - 1;
+        }
+        bs.write_bits(value, bits as u8)?;
+        left -= bits;
+    }
+    Ok(())
+}
+
+// One length prefixed frame, padded out to at least 4 bytes like the game
+// does, or with the padding it was read with if that still lines up.
+// `inner_stream` is only scratch space, so it can be reused between blocks.
+pub(crate) fn write_block<W: BitWrite + ?Sized>(
+    bs: &mut W,
+    inner_stream: &mut BitWriter,
+    frame: &Frame,
+    padding: Option<&RawBits>,
+    policy: Quantization,
+) -> Result<()> {
+    inner_stream.clear();
+    frame.write_to(inner_stream, policy)?;
+
+    let padding = padding.filter(|padding| {
+        let bits = inner_stream.len_bits() + padding.len_bits;
+        bits.is_multiple_of(8) && (8..=255 * 8).contains(&bits)
+    });
+    if let Some(padding) = padding {
+        padding.write(inner_stream)?;
+    }
+
+    let bytes = inner_stream.as_bytes();
+    let len = if padding.is_some() { bytes.len() } else { max(bytes.len(), 4) };
+    let extra = len - bytes.len();
+    bs.write_u8(len as u8)?;
+    write_raw(bs, bytes, bytes.len() * 8)?;
+    bs.write_bits(0, extra as u8 * 8)
+}
+
+// Keeps a copy of every bit read through it, so a lossless read can hold on
+// to a block that turns out to be cut short. Only the single byte read is
+// passed through, so a wide read that fails part way still gets recorded up
+// to where it failed.
+struct Recorder<'a, R: ?Sized> {
+    inner: &'a mut R,
+    bits: BitWriter,
+}
+
+impl<'a, R: BitRead + ?Sized> Recorder<'a, R> {
+    fn new(inner: &'a mut R) -> Recorder<'a, R> {
+        Recorder {
+            inner,
+            bits: BitWriter::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.bits.clear();
+    }
+
+    fn take(&mut self) -> RawBits {
+        RawBits::from(mem::take(&mut self.bits))
+    }
+}
+
+impl<'a, R: BitRead + ?Sized> BitRead for Recorder<'a, R> {
+    fn read_bits_u8(&mut self, bits: u8) -> Result<u8> {
+        let value = self.inner.read_bits_u8(bits)?;
+        self.bits.write_bits_u8(value, bits)?;
+        Ok(value)
+    }
+
+    fn eof(&mut self) -> bool {
+        self.inner.eof()
+    }
+
+    fn begin_field(&mut self, name: &'static str) {
+        self.inner.begin_field(name);
+    }
+
+    fn end_field(&mut self, value: StdResult<FieldValue, &Error>) {
+        self.inner.end_field(value);
+    }
 }
 
 impl Recording {
-    pub fn from_stream(bs: &mut BitStream) -> Result<Recording> {
-        let mission = bs.read_string()?;
-        let mut frames = vec![];
+    pub fn from_bytes(data: &[u8]) -> Result<Recording> {
+        Recording::from_stream(&mut BitReader::new(data))
+    }
+
+    pub fn from_bytes_with(data: &[u8], options: &ReadOptions) -> Result<Recording> {
+        Recording::from_stream_with(&mut BitReader::new(data), options)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read>(reader: R) -> Result<Recording> {
+        Recording::from_stream(&mut IoBitReader::from_reader(reader))
+    }
 
+    pub fn from_stream<R: BitRead>(bs: &mut R) -> Result<Recording> {
+        Recording::from_stream_with(bs, &ReadOptions::default())
+    }
+
+    // Read with a different mission string codec, or keep the block layout
+    // so the rec can be written back exactly
+    pub fn from_stream_with<R: BitRead>(bs: &mut R, options: &ReadOptions) -> Result<Recording> {
+        let (mission, mission_encoding) = bs.read_field("mission", |bs| bs.read_encoded_string_with(options.codec))?;
+        let (frames, framing) = if options.lossless {
+            let (frames, framing) = Recording::read_blocks_lossless(bs)?;
+            (frames, Some(framing))
+        } else {
+            (Recording::read_blocks(bs)?, None)
+        };
+
+        Ok(Recording {
+            mission,
+            frames,
+            mission_encoding,
+            framing,
+            metadata: None,
+        })
+    }
+
+    fn read_blocks<R: BitRead>(bs: &mut R) -> Result<Vec<Frame>> {
+        let mut frames = vec![];
         while !bs.eof() {
-            let length = bs.read_u8()?;
-            if length == 0 {
-                break;
+            match bs.read_field("block", |bs| Recording::read_block(bs, false))? {
+                Block::Frame(frame, _) => frames.push(frame),
+                Block::End | Block::Truncated => break,
             }
+        }
+        Ok(frames)
+    }
 
-            let mut data = Vec::with_capacity(length as usize);
-            for _ in 0..length {
-                if bs.eof() {
-                    return Ok(Recording { mission, frames });
+    // Same blocks, but every bit that isn't part of a frame goes in the framing
+    fn read_blocks_lossless<R: BitRead>(bs: &mut R) -> Result<(Vec<Frame>, Framing)> {
+        let mut frames = vec![];
+        let mut framing = Framing::default();
+        let mut recorder = Recorder::new(bs);
+        while !recorder.eof() {
+            match recorder.read_field("block", |bs| Recording::read_block(bs, true))? {
+                Block::Frame(frame, padding) => {
+                    frames.push(frame);
+                    framing.padding.push(padding);
                 }
-                data.push(bs.read_u8()?);
+                Block::End => {
+                    framing.terminated = true;
+                    break;
+                }
+                // What there was of the block ends up in the trailing bits
+                Block::Truncated => break,
             }
+            recorder.clear();
+        }
+        if framing.terminated {
+            recorder.clear();
+        }
+
+        // A bit at a time, a wider read at the very end would fail without
+        // saying how much was left
+        while !recorder.eof() {
+            recorder.read_bits_u8(1)?;
+        }
+        framing.trailing = recorder.take();
+        Ok((frames, framing))
+    }
 
-            let mut inner_stream = BitStream::new(data);
-            frames.push(Frame::from_stream(&mut inner_stream)?);
+    // One length prefixed frame. The frame is read straight out of the outer
+    // stream, so nothing gets copied or allocated for it.
+    pub(crate) fn read_block<R: BitRead>(bs: &mut R, lossless: bool) -> Result<Block> {
+        let length = match bs.read_field("length", |bs| bs.read_u8()) {
+            Ok(length) => length as usize,
+            // Only padding left after an unaligned mission string
+            Err(ref e) if e.is_eof() => return Ok(Block::Truncated),
+            Err(e) => return Err(e),
+        };
+        if length == 0 {
+            return Ok(Block::End);
         }
 
-        Ok(Recording { mission, frames })
+        let mut block = BitTake::new(bs, length * 8);
+        let frame = match block.read_field("frame", Frame::from_stream) {
+            Ok(frame) => frame,
+            // The file ended part way through the block, keep what we have
+            Err(ref e) if e.is_eof() && !block.limit_reached() => return Ok(Block::Truncated),
+            Err(e) => return Err(e),
+        };
+        let padding = if lossless {
+            block.read_field("padding", |bs| {
+                let bits = bs.remaining_bits();
+                RawBits::read(bs, bits)
+            })
+        } else {
+            block.read_field("padding", |bs| bs.skip_rest()).map(|()| RawBits::default())
+        };
+        match padding {
+            Ok(padding) => Ok(Block::Frame(frame, padding)),
+            Err(ref e) if e.is_eof() => Ok(Block::Truncated),
+            Err(e) => Err(e),
+        }
     }
 
-    pub fn into_stream(self, bs: &mut BitStream) -> Result<()> {
-        bs.write_string(self.mission)?;
+    pub fn into_bytes(self) -> Result<Vec<u8>> {
+        let mut os = BitWriter::new();
+        self.into_stream(&mut os)?;
+        Ok(os.bytes())
+    }
 
-        for frame in self.frames {
-            let mut inner_stream = BitStream::new(vec![]);
-            frame.into_stream(&mut inner_stream)?;
+    #[cfg(feature = "std")]
+    pub fn into_writer<W: Write>(self, writer: W) -> Result<W> {
+        let mut os = IoBitWriter::new(writer);
+        self.into_stream(&mut os)?;
+        os.finish()
+    }
 
-            let bytes = inner_stream.bytes();
-            let len = max(bytes.len(), 4);
-            let extra = len - bytes.len();
-            bs.write_u8(len as u8)?;
+    pub fn into_stream<W: BitWrite>(self, bs: &mut W) -> Result<()> {
+        self.into_stream_with(bs, &WriteOptions::default())
+    }
 
-            for byte in bytes {
-                bs.write_u8(byte)?;
-            }
-            for _ in 0..extra {
-                bs.write_u8(0u8)?;
+    // Write with a different mission string codec (readers have to be told the
+    // same codec to get it back out) or quantization policy. Frames with
+    // framing from a lossless read keep their original blocks, as long as
+    // they still line up with them.
+    pub fn into_stream_with<W: BitWrite>(self, bs: &mut W, options: &WriteOptions) -> Result<()> {
+        bs.write_encoded_string_with(&self.mission, self.mission_encoding, options.codec)?;
+
+        let mut inner_stream = BitWriter::with_capacity(16);
+        for (i, frame) in self.frames.iter().enumerate() {
+            let padding = self.framing.as_ref().and_then(|framing| framing.padding.get(i));
+            write_block(bs, &mut inner_stream, frame, padding, options.quantization)?;
+        }
+
+        if let Some(framing) = &self.framing {
+            if framing.terminated {
+                bs.write_u8(0)?;
             }
+            framing.trailing.write(bs)?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quantize::{OutOfRange, Rounding};
+
+    const MISSION: &[u8] = b"marble/data/missions/beginner/movement.mis";
+
+    fn frame(delta: u16, yaw: Option<f64>, mx: f64) -> Frame {
+        let values = MoveValues {
+            yaw,
+            pitch: None,
+            roll: Some(-PI),
+            mx,
+            my: 0.25,
+            mz: -1f64,
+            freelook: true,
+            triggers: Trigger::Jump | Trigger::Fire,
+        };
+        let (mv, _) = values.quantize(Quantization::default()).unwrap();
+        Frame {
+            moves: [Some(mv), None],
+            delta,
+        }
+    }
+
+    fn frames() -> Vec<Frame> {
+        vec![frame(16, Some(0.5), 0.0625), frame(17, None, -1f64), frame(1023, Some(3.0), 2.9375)]
+    }
+
+    // A rec the game would never write: every block has ones in its padding
+    // and a byte more than it needs
+    fn odd_blocks(frames: &[Frame]) -> BitWriter {
+        let mut bs = BitWriter::new();
+        bs.write_string_bytes(MISSION).unwrap();
+        for frame in frames {
+            let mut block = BitWriter::new();
+            frame.clone().into_stream(&mut block).unwrap();
+            let bits = (8 - block.len_bits() % 8) % 8 + 8;
+            block.write_bits(0x5A5 & ((1 << bits) - 1), bits as u8).unwrap();
+            bs.write_u8(block.len() as u8).unwrap();
+            for &byte in block.as_bytes() {
+                bs.write_u8(byte).unwrap();
+            }
+        }
+        bs
+    }
+
+    fn lossless() -> ReadOptions {
+        ReadOptions {
+            lossless: true,
+            ..ReadOptions::default()
+        }
+    }
+
+    #[test]
+    fn lossless_keeps_odd_padding() {
+        let mut bs = odd_blocks(&frames());
+        bs.write_u8(0).unwrap();
+        // Junk after the end block
+        bs.write_u8(0xDE).unwrap();
+        bs.write_bits(0x5, 3).unwrap();
+        let bytes = bs.bytes();
+
+        let rec = Recording::from_bytes_with(&bytes, &lossless()).unwrap();
+        let framing = rec.framing.clone().unwrap();
+        assert!(framing.terminated);
+        assert_eq!(framing.padding.len(), 3);
+        assert!(framing.padding.iter().all(|padding| padding.bytes.iter().any(|&b| b != 0)));
+        assert_eq!(framing.trailing.len_bits, 16);
+        assert_eq!(rec.clone().into_bytes().unwrap(), bytes);
+
+        // Without framing the blocks get the game's padding instead
+        let plain = Recording::from_bytes(&bytes).unwrap();
+        assert_ne!(plain.into_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn lossless_keeps_a_cut_short_block() {
+        let mut bs = odd_blocks(&frames());
+        // Says 9 bytes, has 3
+        for &byte in &[9u8, 1, 2, 3] {
+            bs.write_u8(byte).unwrap();
+        }
+        let bytes = bs.bytes();
+
+        let rec = Recording::from_bytes_with(&bytes, &lossless()).unwrap();
+        let framing = rec.framing.clone().unwrap();
+        assert!(!framing.terminated);
+        assert_eq!(framing.trailing.bytes, [9, 1, 2, 3]);
+        assert_eq!(rec.frames.len(), 3);
+        assert_eq!(rec.into_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn edited_frames_fall_back_to_game_padding() {
+        let mut bs = odd_blocks(&frames());
+        bs.write_u8(0).unwrap();
+        let bytes = bs.bytes();
+
+        let mut rec = Recording::from_bytes_with(&bytes, &lossless()).unwrap();
+        let padding = rec.framing.clone().unwrap().padding;
+        // A whole move more, which isn't a multiple of 8 bits, so the old
+        // padding no longer lines up
+        rec.frames[1].moves[1] = rec.frames[1].moves[0];
+        let back = Recording::from_bytes_with(&rec.into_bytes().unwrap(), &lossless()).unwrap();
+        let framing = back.framing.unwrap();
+        assert_eq!(framing.padding[0], padding[0]);
+        assert_eq!(Some(&framing.padding[1]), RawBits::game_padding(&back.frames[1]).as_ref());
+        assert_eq!(framing.padding[2], padding[2]);
+    }
+
+    #[test]
+    fn just_under_two_pi_is_zero() {
+        let policy = Quantization::new(Rounding::Nearest, OutOfRange::Error);
+        let step = 2f64 * PI / 65536f64;
+        for &angle in &[2f64 * PI - step / 4f64, -step / 4f64, -1e-12] {
+            let (raw, error) = Angle::from_f64(angle, policy).unwrap();
+            assert_eq!(raw, Angle(0));
+            assert!(error.abs() < step / 2f64);
+        }
+        // Floor never rounds up into the next turn
+        let floor = Quantization::new(Rounding::Floor, OutOfRange::Error);
+        assert_eq!(Angle::from_f64(2f64 * PI - step / 4f64, floor).unwrap().0, Angle(65535));
+    }
+
+    #[test]
+    fn floor_rounds_down_between_steps() {
+        let floor = Quantization::new(Rounding::Floor, OutOfRange::Error);
+        let nearest = Quantization::new(Rounding::Nearest, OutOfRange::Error);
+        let step = 2f64 * PI / 65536f64;
+        assert_eq!(Angle::from_f64(step * 2.7, floor).unwrap().0, Angle(2));
+        assert_eq!(Angle::from_f64(step * 2.7, nearest).unwrap().0, Angle(3));
+        assert_eq!(Axis::from_f64(0.1, floor).unwrap().0, Axis(17));
+        assert_eq!(Axis::from_f64(0.1, nearest).unwrap().0, Axis(18));
+        let (_, error) = Axis::from_f64(0.1, floor).unwrap();
+        assert!(error < 0f64 && error > -Axis::SCALE);
+    }
+
+    // What `((value - offset) / scale) as u64` wrote before there was a
+    // policy, which the default still has to match
+    #[test]
+    fn default_writes_the_legacy_bytes() {
+        let step = 2f64 * PI / 65536f64;
+        let values = MoveValues {
+            yaw: Some(step * 2.7),
+            pitch: Some(-step * 0.3),
+            roll: None,
+            mx: 0.1,
+            my: -0.99,
+            mz: 2.9,
+            freelook: false,
+            triggers: Triggers::empty(),
+        };
+        let (mv, error) = values.quantize(Quantization::default()).unwrap();
+        assert_eq!(mv.yaw, Some(Angle((step * 2.7 / ANGLE_SCALE) as u16)));
+        assert_eq!(mv.pitch, Some(Angle(((2f64 * PI - step * 0.3) / ANGLE_SCALE) as u16)));
+        assert_eq!([mv.mx, mv.my, mv.mz], [Axis(17), Axis(0), Axis(62)]);
+        assert!(error.mx < 0f64 && error.mx > -Axis::SCALE);
+
+        let mut bs = BitWriter::new();
+        mv.into_stream(&mut bs).unwrap();
+        assert_eq!(bs.bytes(), [0x05, 0x00, 0xFE, 0xFF, 0x8B, 0x00, 0x1F, 0x00]);
+
+        let mut bs = BitWriter::new();
+        bs.write_scaled_f64_bits(0.1, Axis::BITS, Axis::SCALE, Axis::OFFSET).unwrap();
+        assert_eq!(bs.bytes(), [17]);
+    }
+
+    #[test]
+    fn every_step_round_trips() {
+        let roundings = [Rounding::Truncate, Rounding::Nearest, Rounding::Floor];
+        for policy in roundings.iter().map(|&rounding| Quantization::new(rounding, OutOfRange::Error)) {
+            for raw in 0..=u16::MAX {
+                assert_eq!(Angle::from_f64(Angle(raw).to_f64(), policy).unwrap().0, Angle(raw), "angle {}", raw);
+            }
+            for raw in 0..64 {
+                assert_eq!(Axis::from_f64(Axis(raw).to_f64(), policy).unwrap().0, Axis(raw), "axis {}", raw);
+            }
+        }
+    }
+
+    #[test]
+    fn wide_axis_is_an_error() {
+        let mut bs = BitWriter::new();
+        assert!(Axis(63).write_to(&mut bs, Quantization::default()).is_ok());
+        assert!(Axis(64).write_to(&mut bs, Quantization::default()).is_err());
+    }
+
+    fn raw_frames() -> Vec<Frame> {
+        let angles = [0u16, 1, 32767, 32768, 32769, 65535];
+        let mut frames = Vec::new();
+        for (i, &angle) in angles.iter().enumerate() {
+            let mv = Move {
+                yaw: Some(Angle(angle)),
+                pitch: if i % 2 == 0 { None } else { Some(Angle(u16::MAX - angle)) },
+                roll: Some(Angle(angle ^ 0x5555)),
+                mx: Axis(i as u8),
+                my: Axis(63 - i as u8),
+                mz: Axis(16),
+                freelook: i % 3 == 0,
+                triggers: Trigger::Jump.into(),
+            };
+            frames.push(Frame {
+                moves: [Some(mv), if i % 2 == 0 { Some(mv) } else { None }],
+                delta: 1 + i as u16 * 200,
+            });
+        }
+        frames
+    }
+
+    #[cfg(feature = "json")]
+    fn raw_bytes() -> Vec<u8> {
+        let mut bs = BitWriter::new();
+        bs.write_string_bytes(MISSION).unwrap();
+        let mut inner = BitWriter::new();
+        for frame in &raw_frames() {
+            write_block(&mut bs, &mut inner, frame, None, Quantization::default()).unwrap();
+        }
+        // No end block, like the game's own writer
+        bs.bytes()
+    }
+
+    // Every move in the JSON, to poke at
+    #[cfg(feature = "json")]
+    fn json_moves(json: &mut serde_json::Value) -> impl Iterator<Item = &mut serde_json::Value> {
+        json["frames"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .flat_map(|frame| frame["moves"].as_array_mut().unwrap().iter_mut())
+            .filter(|mv| !mv.is_null())
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trips_bit_for_bit() {
+        let bytes = raw_bytes();
+        let rec = Recording::from_bytes(&bytes).unwrap();
+        let json = serde_json::to_string(&rec).unwrap();
+        let back = serde_json::from_str::<Recording>(&json).unwrap();
+        assert_eq!(back.into_bytes().unwrap(), bytes);
+
+        // The floats alone get there too
+        let mut json = serde_json::to_value(&rec).unwrap();
+        for mv in json_moves(&mut json) {
+            assert!(mv.as_object_mut().unwrap().remove("raw").is_some());
+        }
+        let back = serde_json::from_value::<Recording>(json).unwrap();
+        assert_eq!(back.into_bytes().unwrap(), bytes);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn edited_json_floats_win_over_raw() {
+        let rec = Recording::from_bytes(&raw_bytes()).unwrap();
+        let mut json = serde_json::to_value(&rec).unwrap();
+        json_moves(&mut json).next().unwrap()["mx"] = serde_json::json!(0.5);
+        let back = serde_json::from_value::<Recording>(json).unwrap();
+        let mv = back.frames[0].first_move().unwrap();
+        assert_eq!(mv.mx, Axis(24));
+        assert_eq!(mv.yaw, rec.frames[0].first_move().unwrap().yaw);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn wide_raw_axis_from_json_is_an_error() {
+        let mut rec = Recording::from_bytes(&odd_blocks(&frames()).bytes()).unwrap();
+        rec.frames[0].moves[0].as_mut().unwrap().mx = Axis(64);
+        let json = serde_json::to_string(&rec).unwrap();
+        let back = serde_json::from_str::<Recording>(&json).unwrap();
+        assert_eq!(back.frames[0].first_move().unwrap().mx, Axis(64));
+        assert!(back.into_bytes().is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn lossless_survives_json() {
+        let mut bs = odd_blocks(&frames());
+        bs.write_u8(0).unwrap();
+        bs.write_u8(0xFF).unwrap();
+        let bytes = bs.bytes();
+
+        let rec = Recording::from_bytes_with(&bytes, &lossless()).unwrap();
+        let json = serde_json::to_string(&rec).unwrap();
+        let back = serde_json::from_str::<Recording>(&json).unwrap();
+        assert_eq!(back.into_bytes().unwrap(), bytes);
+    }
+}
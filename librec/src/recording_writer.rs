@@ -0,0 +1,165 @@
+use std::io::{BufWriter, Write};
+use crate::bit_io::IoBitWriter;
+use crate::bit_stream::{BitWrite, BitWriter};
+use crate::encoding::Encoding;
+use crate::error::Result;
+use crate::error::ErrorKind::GenericError2;
+use crate::recording::{write_block, Frame, WriteOptions};
+
+// Writes a rec a frame at a time instead of building a whole Recording first.
+// What comes out is byte for byte what Recording::into_stream writes for the
+// same frames, so like the game there's no zero length block at the end.
+pub struct RecordingWriter<W: Write> {
+    bs: IoBitWriter<BufWriter<W>>,
+    options: WriteOptions,
+    inner_stream: BitWriter,
+    frames: usize,
+    elapsed: u64,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    pub fn new(writer: W, mission: &str) -> Result<RecordingWriter<W>> {
+        RecordingWriter::with_options(writer, mission, Encoding::Utf8, &WriteOptions::default())
+    }
+
+    // Writes the mission header straight away
+    pub fn with_options(
+        writer: W,
+        mission: &str,
+        mission_encoding: Encoding,
+        options: &WriteOptions,
+    ) -> Result<RecordingWriter<W>> {
+        let mut bs = IoBitWriter::new(BufWriter::new(writer));
+        bs.write_encoded_string_with(mission, mission_encoding, options.codec)?;
+        Ok(RecordingWriter {
+            bs,
+            options: *options,
+            inner_stream: BitWriter::with_capacity(16),
+            frames: 0,
+            elapsed: 0,
+        })
+    }
+
+    // A frame that can't be written (delta too long, an axis step past its 6
+    // bits) is rejected before any of it is written, so the rec stays valid
+    // and more frames can still be pushed.
+    pub fn push_frame(&mut self, frame: &Frame) -> Result<()> {
+        if frame.delta > Frame::MAX_DELTA {
+            return Err(GenericError2(format!(
+                "Frame {} delta {} is longer than the {} ms a frame can hold",
+                self.frames, frame.delta, Frame::MAX_DELTA
+            ))
+            .into());
+        }
+        write_block(&mut self.bs, &mut self.inner_stream, frame, None, self.options.quantization)?;
+        self.frames += 1;
+        self.elapsed += u64::from(frame.delta);
+        Ok(())
+    }
+
+    pub fn frames_written(&self) -> usize {
+        self.frames
+    }
+
+    // Milliseconds covered by the frames written so far
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    // Flush and hand back the writer
+    pub fn finish(self) -> Result<W> {
+        let buffered = self.bs.finish()?;
+        Ok(buffered.into_inner().map_err(|e| e.into_error())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::StringCodec;
+    use crate::recording::{Angle, Axis, Move, Recording, Triggers};
+
+    const MISSION: &str = "marble/data/missions/beginner/movement.mis";
+
+    fn frames() -> Vec<Frame> {
+        (0..20u16)
+            .map(|i| {
+                let mv = Move {
+                    yaw: if i % 2 == 0 { Some(Angle(i * 3000)) } else { None },
+                    pitch: None,
+                    roll: Some(Angle(65535 - i)),
+                    mx: Axis(i as u8),
+                    my: Axis(63),
+                    mz: Axis(16),
+                    freelook: i % 3 == 0,
+                    triggers: Triggers::from_bits(i as u8),
+                };
+                Frame {
+                    moves: [Some(mv), if i % 4 == 0 { None } else { Some(mv) }],
+                    delta: if i == 7 { Frame::MAX_DELTA } else { 16 + i % 2 },
+                }
+            })
+            .collect()
+    }
+
+    fn rec(mission_encoding: Encoding) -> Recording {
+        Recording {
+            mission: MISSION.to_string(),
+            frames: frames(),
+            mission_encoding,
+            framing: None,
+            metadata: None,
+        }
+    }
+
+    fn write(options: &WriteOptions, mission_encoding: Encoding) -> Vec<u8> {
+        let mut writer = RecordingWriter::with_options(Vec::new(), MISSION, mission_encoding, options).unwrap();
+        for frame in &frames() {
+            writer.push_frame(frame).unwrap();
+        }
+        assert_eq!(writer.frames_written(), 20);
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn writes_the_same_bytes_as_a_recording() {
+        let huffman = WriteOptions {
+            codec: StringCodec::Huffman,
+            ..WriteOptions::default()
+        };
+        for options in &[WriteOptions::default(), huffman] {
+            for &encoding in &[Encoding::Utf8, Encoding::Latin1] {
+                let mut os = BitWriter::new();
+                rec(encoding).into_stream_with(&mut os, options).unwrap();
+                assert_eq!(write(options, encoding), os.bytes(), "{:?} {:?}", options.codec, encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn writes_the_same_bytes_as_into_writer() {
+        assert_eq!(write(&WriteOptions::default(), Encoding::Utf8), rec(Encoding::Utf8).into_writer(Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn over_long_deltas_are_rejected() {
+        let mut writer = RecordingWriter::new(Vec::new(), MISSION).unwrap();
+        let mut frames = frames();
+        writer.push_frame(&frames[0]).unwrap();
+        frames[1].delta = Frame::MAX_DELTA + 1;
+        assert!(writer.push_frame(&frames[1]).is_err());
+        // Nothing of it was written, and the next frame still goes in
+        frames[1].delta = 16;
+        frames[2].moves[0].as_mut().unwrap().mx = Axis(64);
+        assert!(writer.push_frame(&frames[2]).is_err());
+        writer.push_frame(&frames[1]).unwrap();
+        assert_eq!(writer.frames_written(), 2);
+        assert_eq!(writer.elapsed(), 32);
+
+        let bytes = writer.finish().unwrap();
+        let rec = Recording::from_bytes(&bytes).unwrap();
+        assert_eq!(rec.frames.len(), 2);
+        assert_eq!(rec.frames[1].moves, frames[1].moves);
+        assert_eq!(rec.duration(), 32);
+    }
+}
@@ -0,0 +1,284 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::max;
+use core::result::Result as StdResult;
+use serde::Serialize;
+use crate::bit_stream::{BitRead, BitReader, BitTake};
+use crate::error::Result;
+use crate::huffman::StringCodec;
+use crate::recording::{Frame, Framing, RawBits, Recording};
+
+// Number of good blocks in a row it takes to trust a place to pick up
+// reading again after damage
+const RESYNC_BLOCKS: usize = 3;
+
+// A stretch of a rec that had to be skipped
+#[derive(Debug, Clone, Serialize)]
+pub struct Damage {
+    // Byte the damage starts at
+    pub offset: usize,
+    // Bytes skipped, up to where reading picked up again
+    pub length: usize,
+    // Index the next recovered frame got, so the damage sits right before it
+    pub frame: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Salvage {
+    pub recording: Recording,
+    pub damage: Vec<Damage>,
+}
+
+impl Salvage {
+    pub fn is_clean(&self) -> bool {
+        self.damage.is_empty()
+    }
+}
+
+pub fn salvage(data: &[u8]) -> Result<Salvage> {
+    salvage_with(data, StringCodec::Raw)
+}
+
+// Read as much of a damaged rec as possible. Blocks that don't decode are
+// skipped by scanning forward for the next place where a few blocks in a row
+// look exactly like the game would write them, and everything skipped gets
+// reported. Only a broken mission string fails the whole thing, without it
+// there's nowhere to start from. Anything after the end block that isn't more
+// blocks is kept in the recording's framing and written back after it.
+pub fn salvage_with(data: &[u8], codec: StringCodec) -> Result<Salvage> {
+    let mut bs = BitReader::new(data);
+    let (mission, mission_encoding) = bs.read_encoded_string_with(codec)?;
+    let len_bits = data.len() * 8;
+    let mut position = bs.position().bits();
+    let mut frames = vec![];
+    let mut damage = vec![];
+    let mut framing = None;
+
+    // Under a byte left is just the rest of the mission string's last byte
+    while len_bits - position >= 8 {
+        let reason = match read_block(data, position) {
+            // A block that isn't quite how the game writes them is only
+            // trusted if the blocks after it still line up
+            Ok(Some(block)) if block.exact || len_bits - block.next < 8 || is_resync_point(data, block.next) => {
+                frames.push(block.frame);
+                position = block.next;
+                continue;
+            }
+            Ok(Some(_)) => "Block length does not match its frame".to_string(),
+            // Zeroes all the way to the end are just padding after the end
+            // block, anything else means the length was damaged
+            Ok(None) if is_zeroed(data, position) => break,
+            // Only a damaged length if blocks pick up again after it,
+            // otherwise it's the real end and the rest is something else
+            Ok(None) if next_resync_point(data, position).is_none() => {
+                let mut bs = reader_at(data, position + 8);
+                framing = Some(Framing {
+                    padding: vec![],
+                    terminated: true,
+                    trailing: RawBits::read(&mut bs, len_bits - position - 8)?,
+                });
+                break;
+            }
+            Ok(None) => "End block before the end of the rec".to_string(),
+            Err(bad) => bad.reason,
+        };
+
+        let end = next_resync_point(data, position).unwrap_or(len_bits);
+        damage.push(Damage {
+            offset: position / 8,
+            length: end.div_ceil(8) - position / 8,
+            frame: frames.len(),
+            reason,
+        });
+        position = end;
+    }
+
+    Ok(Salvage {
+        recording: Recording {
+            mission,
+            frames,
+            mission_encoding,
+            framing,
+            metadata: None,
+        },
+        damage,
+    })
+}
+
+struct Block {
+    frame: Frame,
+    // Where the next block starts
+    next: usize,
+    // Written exactly like the game does it: the smallest length that fits
+    // the frame, at least 4 bytes, and nothing but zeroes after the frame
+    exact: bool,
+}
+
+struct BadBlock {
+    // Ran into the end of the data rather than being broken itself
+    cut_off: bool,
+    reason: String,
+}
+
+// The block at `position`, or None for the end block
+fn read_block(data: &[u8], position: usize) -> StdResult<Option<Block>, BadBlock> {
+    let mut bs = reader_at(data, position);
+    let length = match bs.read_u8() {
+        Ok(length) => length as usize,
+        Err(e) => {
+            return Err(BadBlock {
+                cut_off: true,
+                reason: e.to_string(),
+            })
+        }
+    };
+    if length == 0 {
+        return Ok(None);
+    }
+    let block_bits = length * 8;
+    if bs.remaining_bits() < block_bits {
+        return Err(BadBlock {
+            cut_off: true,
+            reason: format!("Block of {} bytes cut off after {} bytes", length, bs.remaining_bits() / 8),
+        });
+    }
+
+    let mut block = BitTake::new(&mut bs, block_bits);
+    let frame = match Frame::from_stream(&mut block) {
+        Ok(frame) => frame,
+        Err(e) => {
+            let reason = if block.limit_reached() {
+                format!("Frame does not fit in its {} byte block", length)
+            } else {
+                e.to_string()
+            };
+            return Err(BadBlock { cut_off: false, reason });
+        }
+    };
+    let padding = block.remaining_bits();
+    let frame_bytes = (block_bits - padding).div_ceil(8);
+    let exact = length == max(frame_bytes, 4) && is_zeroed_bits(&mut block, padding);
+    Ok(Some(Block {
+        frame,
+        next: position + 8 + block_bits,
+        exact,
+    }))
+}
+
+// Whether the next few blocks from `position` are all exact. Running into the
+// end of the data or a real end block part way counts too.
+fn is_resync_point(data: &[u8], mut position: usize) -> bool {
+    let len_bits = data.len() * 8;
+    for blocks in 0..RESYNC_BLOCKS {
+        if len_bits - position < 8 {
+            return blocks > 0;
+        }
+        match read_block(data, position) {
+            Ok(Some(block)) if block.exact => position = block.next,
+            Ok(Some(_)) => return false,
+            Ok(None) => return is_zeroed(data, position),
+            Err(bad) => return bad.cut_off && blocks > 0,
+        }
+    }
+    true
+}
+
+// First byte after `position` that blocks can be read from again
+fn next_resync_point(data: &[u8], position: usize) -> Option<usize> {
+    (position + 8..data.len() * 8)
+        .step_by(8)
+        .find(|&candidate| is_resync_point(data, candidate))
+}
+
+fn reader_at(data: &[u8], position: usize) -> BitReader<'_> {
+    let mut bs = BitReader::new(data);
+    bs.seek(position / 8, (position % 8) as u8);
+    bs
+}
+
+// Everything from `position` to the end is zero
+fn is_zeroed(data: &[u8], position: usize) -> bool {
+    let mut bs = reader_at(data, position);
+    let bits = bs.remaining_bits();
+    is_zeroed_bits(&mut bs, bits)
+}
+
+fn is_zeroed_bits<R: BitRead>(bs: &mut R, mut bits: usize) -> bool {
+    while bits > 0 {
+        let chunk = bits.min(64) as u8;
+        match bs.read_bits(chunk) {
+            Ok(0) => bits -= chunk as usize,
+            _ => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_stream::{BitWrite, BitWriter};
+    use crate::quantize::Quantization;
+    use crate::recording::{write_block, Angle, Axis, Move, Triggers};
+
+    fn frame(delta: u16) -> Frame {
+        let mv = Move {
+            yaw: Some(Angle(5215)),
+            pitch: None,
+            roll: None,
+            mx: Axis(20),
+            my: Axis(0),
+            mz: Axis(16),
+            freelook: false,
+            triggers: Triggers::default(),
+        };
+        Frame {
+            moves: [Some(mv), None],
+            delta,
+        }
+    }
+
+    fn rec(frames: usize) -> BitWriter {
+        let mut bs = BitWriter::new();
+        bs.write_string_bytes(b"marble/data/missions/beginner/movement.mis").unwrap();
+        let mut inner = BitWriter::new();
+        for i in 0..frames {
+            write_block(&mut bs, &mut inner, &frame(16 + i as u16), None, Quantization::default()).unwrap();
+        }
+        bs
+    }
+
+    #[test]
+    fn stops_at_the_end_block() {
+        let mut bs = rec(10);
+        for &byte in &[0u8, 0xDE, 0xAD, 0x01] {
+            bs.write_u8(byte).unwrap();
+        }
+        let data = bs.bytes();
+
+        let salvage = salvage(&data).unwrap();
+        assert!(salvage.is_clean());
+        assert_eq!(salvage.recording.frames.len(), 10);
+        let framing = salvage.recording.framing.clone().unwrap();
+        assert_eq!(framing.trailing.bytes, [0xDE, 0xAD, 0x01]);
+        assert_eq!(salvage.recording.into_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn zeroed_length_before_more_blocks_is_damage() {
+        let mut data = rec(10).bytes();
+        // Length of the fourth block
+        let block = rec(3).bytes().len();
+        data[block] = 0;
+
+        let salvage = salvage(&data).unwrap();
+        assert_eq!(salvage.damage.len(), 1);
+        assert_eq!(salvage.damage[0].frame, 3);
+        assert_eq!(salvage.damage[0].offset, block);
+        assert_eq!(salvage.recording.frames.len(), 9);
+        assert!(salvage.recording.framing.is_none());
+    }
+}
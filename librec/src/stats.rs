@@ -0,0 +1,363 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(feature = "json")]
+use alloc::string::String;
+use serde::Serialize;
+#[cfg(feature = "json")]
+use crate::error::Result;
+use crate::recording::{Frame, Move, Recording, Trigger, TICK_MS};
+
+// Scales the median absolute deviation to a standard deviation for normally
+// distributed frame times
+const MAD_SCALE: f64 = 1.4826;
+
+// Frame times further than this many (scaled) deviations from the median
+// don't count towards the FPS
+const OUTLIER_DEVIATIONS: f64 = 3.0;
+
+// Fewer frames than this say nothing useful about the frame rate
+const MIN_FPS_FRAMES: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FrameTimes {
+    pub min: u16,
+    pub p50: u16,
+    pub p90: u16,
+    pub p99: u16,
+    pub max: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TriggerStats {
+    // Times it went from not held to held
+    pub presses: usize,
+    // Game time it was held for, a tick per move
+    pub held_ms: u64,
+    pub longest_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub frames: usize,
+    // Total of every frame's delta, in ms
+    pub duration: u64,
+    // How many frames took each delta, shortest first
+    pub deltas: Vec<(u16, usize)>,
+    pub frame_times: Option<FrameTimes>,
+    // Frames per second once the level is going, leaving out frames that
+    // took far longer or shorter than the rest (loading, alt-tabbing). None
+    // for recs too short to tell.
+    pub fps: Option<f64>,
+    // Frames with a move that does anything: looking around, moving or
+    // holding a trigger
+    pub input_frames: usize,
+    pub input_share: f64,
+    // Indexed by Trigger::index
+    pub triggers: [TriggerStats; 6],
+}
+
+impl Stats {
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+// Builds up Stats a frame at a time, so a FrameReader can be fed straight in
+// without keeping the frames around
+#[derive(Debug, Clone, Default)]
+pub struct StatsBuilder {
+    frames: usize,
+    duration: u64,
+    input_frames: usize,
+    deltas: BTreeMap<u16, usize>,
+    // Deltas from the first frame with a move on, loading takes a while but
+    // has no moves
+    playing: BTreeMap<u16, usize>,
+    triggers: [TriggerStats; 6],
+    // How long each trigger has been held so far
+    holding: [u64; 6],
+}
+
+impl StatsBuilder {
+    pub fn new() -> StatsBuilder {
+        StatsBuilder::default()
+    }
+
+    pub fn push(&mut self, frame: &Frame) {
+        self.frames += 1;
+        self.duration += u64::from(frame.delta);
+        *self.deltas.entry(frame.delta).or_insert(0) += 1;
+        if frame.has_move() || !self.playing.is_empty() {
+            *self.playing.entry(frame.delta).or_insert(0) += 1;
+        }
+
+        let moves = frame.moves.iter().flatten();
+        if moves.clone().any(has_input) {
+            self.input_frames += 1;
+        }
+        for mv in moves {
+            for &trigger in Trigger::ALL.iter() {
+                self.push_trigger(trigger.index(), mv.triggers.pressed(trigger));
+            }
+        }
+    }
+
+    fn push_trigger(&mut self, i: usize, held: bool) {
+        let stats = &mut self.triggers[i];
+        if held {
+            if self.holding[i] == 0 {
+                stats.presses += 1;
+            }
+            self.holding[i] += u64::from(TICK_MS);
+            stats.held_ms += u64::from(TICK_MS);
+            stats.longest_ms = stats.longest_ms.max(self.holding[i]);
+        } else {
+            self.holding[i] = 0;
+        }
+    }
+
+    pub fn finish(self) -> Stats {
+        let frame_times = if self.frames == 0 {
+            None
+        } else {
+            Some(FrameTimes {
+                min: *self.deltas.keys().next().unwrap_or(&0),
+                p50: percentile(&self.deltas, self.frames, 50),
+                p90: percentile(&self.deltas, self.frames, 90),
+                p99: percentile(&self.deltas, self.frames, 99),
+                max: *self.deltas.keys().next_back().unwrap_or(&0),
+            })
+        };
+        // Recs with no moves at all still have a frame rate
+        let fps = if self.playing.is_empty() {
+            robust_fps(&self.deltas)
+        } else {
+            robust_fps(&self.playing)
+        };
+
+        Stats {
+            frames: self.frames,
+            duration: self.duration,
+            deltas: self.deltas.into_iter().collect(),
+            frame_times,
+            fps,
+            input_frames: self.input_frames,
+            input_share: if self.frames == 0 {
+                0f64
+            } else {
+                self.input_frames as f64 / self.frames as f64
+            },
+            triggers: self.triggers,
+        }
+    }
+}
+
+impl Recording {
+    pub fn stats(&self) -> Stats {
+        let mut builder = StatsBuilder::new();
+        for frame in &self.frames {
+            builder.push(frame);
+        }
+        builder.finish()
+    }
+}
+
+fn has_input(mv: &Move) -> bool {
+    mv.yaw.is_some()
+        || mv.pitch.is_some()
+        || mv.roll.is_some()
+        || mv.mx() != 0f64
+        || mv.my() != 0f64
+        || mv.mz() != 0f64
+        || !mv.triggers.is_empty()
+}
+
+// Nearest rank percentile out of a histogram of `count` values
+fn percentile(histogram: &BTreeMap<u16, usize>, count: usize, percent: usize) -> u16 {
+    let rank = (count * percent).div_ceil(100).max(1);
+    let mut seen = 0;
+    for (&value, &n) in histogram {
+        seen += n;
+        if seen >= rank {
+            return value;
+        }
+    }
+    0
+}
+
+// Average FPS over the frames within a few deviations of the median frame
+// time. The deviation is the median absolute deviation, so a handful of
+// huge loading frames can't drag it out the way a standard deviation would.
+fn robust_fps(histogram: &BTreeMap<u16, usize>) -> Option<f64> {
+    let count = histogram.values().sum::<usize>();
+    if count < MIN_FPS_FRAMES {
+        return None;
+    }
+    let median = percentile(histogram, count, 50);
+
+    let mut deviations = BTreeMap::new();
+    for (&value, &n) in histogram {
+        *deviations.entry(value.abs_diff(median)).or_insert(0) += n;
+    }
+    let mad = f64::from(percentile(&deviations, count, 50));
+    // Frame times are whole ms, so never cut closer than one either side
+    let limit = (OUTLIER_DEVIATIONS * MAD_SCALE * mad).max(1f64);
+
+    let (frames, total) = histogram
+        .iter()
+        .filter(|(&value, _)| f64::from(value.abs_diff(median)) <= limit)
+        .fold((0usize, 0u64), |(frames, total), (&value, &n)| {
+            (frames + n, total + u64::from(value) * n as u64)
+        });
+    if total == 0 {
+        return None;
+    }
+    Some(frames as f64 * 1000f64 / total as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use crate::recording::{Axis, Triggers};
+
+    fn mv(triggers: Triggers) -> Move {
+        Move {
+            yaw: None,
+            pitch: None,
+            roll: None,
+            mx: Axis(16),
+            my: Axis(16),
+            mz: Axis(16),
+            freelook: false,
+            triggers,
+        }
+    }
+
+    fn frame(delta: u16, moves: usize, triggers: Triggers) -> Frame {
+        let mv = mv(triggers);
+        Frame {
+            moves: [if moves > 0 { Some(mv) } else { None }, if moves > 1 { Some(mv) } else { None }],
+            delta,
+        }
+    }
+
+    fn stats(frames: &[Frame]) -> Stats {
+        let mut builder = StatsBuilder::new();
+        for frame in frames {
+            builder.push(frame);
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn percentiles_are_nearest_rank() {
+        let frames: Vec<Frame> = (1..=100).rev().map(|delta| frame(delta, 1, Triggers::empty())).collect();
+        let stats = stats(&frames);
+        assert_eq!(
+            stats.frame_times,
+            Some(FrameTimes {
+                min: 1,
+                p50: 50,
+                p90: 90,
+                p99: 99,
+                max: 100,
+            })
+        );
+        assert_eq!(stats.duration, 5050);
+        assert_eq!(stats.deltas.len(), 100);
+        assert_eq!(stats.deltas[0], (1, 1));
+
+        let mut histogram = BTreeMap::new();
+        histogram.insert(16, 7);
+        histogram.insert(17, 2);
+        histogram.insert(400, 1);
+        assert_eq!(percentile(&histogram, 10, 50), 16);
+        assert_eq!(percentile(&histogram, 10, 70), 16);
+        assert_eq!(percentile(&histogram, 10, 71), 17);
+        assert_eq!(percentile(&histogram, 10, 90), 17);
+        assert_eq!(percentile(&histogram, 10, 99), 400);
+        // Rank 1 at the bottom, not rank 0
+        assert_eq!(percentile(&histogram, 10, 0), 16);
+    }
+
+    #[test]
+    fn fps_leaves_out_loading_and_stalls() {
+        let mut frames = vec![frame(600, 0, Triggers::empty()), frame(300, 0, Triggers::empty())];
+        for i in 0..60 {
+            frames.push(frame(16 + i % 2, 1, Triggers::empty()));
+        }
+        // Alt-tabbed for a second, and one frame that came far too quick
+        frames.push(frame(1000, 1, Triggers::empty()));
+        frames.push(frame(1, 1, Triggers::empty()));
+        let stats = stats(&frames);
+        assert_eq!(stats.fps, Some(60f64 * 1000f64 / 990f64));
+        assert_eq!(stats.frame_times.unwrap().max, 1000);
+
+        // A plain average would be dragged way down
+        let plain = frames.len() as f64 * 1000f64 / stats.duration as f64;
+        assert!(plain < 30f64);
+    }
+
+    #[test]
+    fn fps_keeps_a_spread_out_frame_rate() {
+        // Nothing here is an outlier, however uneven
+        let frames: Vec<Frame> = (0..40).map(|i| frame(10 + (i % 4) * 5, 1, Triggers::empty())).collect();
+        let total: u64 = frames.iter().map(|frame| u64::from(frame.delta)).sum();
+        assert_eq!(stats(&frames).fps, Some(40f64 * 1000f64 / total as f64));
+    }
+
+    #[test]
+    fn too_few_frames_have_no_fps() {
+        for count in 0..MIN_FPS_FRAMES {
+            let frames = vec![frame(16, 1, Triggers::empty()); count];
+            let stats = stats(&frames);
+            assert_eq!(stats.fps, None, "{} frames", count);
+            assert_eq!(stats.frame_times.is_some(), count > 0);
+        }
+        let frames = vec![frame(16, 1, Triggers::empty()); MIN_FPS_FRAMES];
+        assert_eq!(stats(&frames).fps, Some(62.5));
+        // Recs without a single move go by every frame
+        let frames = vec![frame(20, 0, Triggers::empty()); MIN_FPS_FRAMES];
+        assert_eq!(stats(&frames).fps, Some(50f64));
+    }
+
+    #[test]
+    fn triggers_are_held_a_tick_per_move() {
+        let jump = Triggers::from(Trigger::Jump);
+        let both = Trigger::Jump | Trigger::Fire;
+        let frames = [
+            frame(16, 1, jump),
+            // Two moves, two ticks
+            frame(40, 2, both),
+            frame(16, 1, jump),
+            frame(16, 1, Triggers::empty()),
+            // No moves doesn't let go
+            frame(8, 0, Triggers::empty()),
+            frame(16, 1, jump),
+            frame(16, 1, both),
+        ];
+        let stats = stats(&frames);
+        let tick = u64::from(TICK_MS);
+        assert_eq!(
+            stats.triggers[Trigger::Jump.index()],
+            TriggerStats {
+                presses: 2,
+                held_ms: 6 * tick,
+                longest_ms: 4 * tick,
+            }
+        );
+        assert_eq!(
+            stats.triggers[Trigger::Fire.index()],
+            TriggerStats {
+                presses: 2,
+                held_ms: 3 * tick,
+                longest_ms: 2 * tick,
+            }
+        );
+        assert_eq!(stats.triggers[Trigger::AltFire.index()], TriggerStats::default());
+        assert_eq!(stats.input_frames, 5);
+        assert!((stats.input_share - 5f64 / 7f64).abs() < 1e-12);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::recording::{Frame, Move, Recording};
+use crate::encoding::Encoding;
+use crate::quantize::Quantization;
+use crate::recording::{Frame, Move, MoveValues, Recording, Trigger, Triggers};
+use crate::timeline::Timeline;
 use nom::branch::alt;
 use nom::bytes::complete::is_not;
 use nom::bytes::complete::{escaped, is_a, tag, take_while};
@@ -47,6 +50,9 @@ impl TasFile {
         Recording {
             mission: self.mission,
             frames,
+            mission_encoding: Encoding::default(),
+            framing: None,
+            metadata: None,
         }
     }
 
@@ -70,22 +76,22 @@ impl TasFile {
         if let Some(mv) = opt_mv {
             out.write_fmt(format_args!(
                 "         camera ({} {} {})\n",
-                mv.yaw.unwrap_or(0f64),
-                mv.pitch.unwrap_or(0f64),
-                mv.roll.unwrap_or(0f64)
+                mv.yaw().unwrap_or(0f64),
+                mv.pitch().unwrap_or(0f64),
+                mv.roll().unwrap_or(0f64)
             ))?;
             out.write_fmt(format_args!(
                 "         move ({} {} {})\n",
-                mv.mx, mv.my, mv.mz
+                mv.mx(), mv.my(), mv.mz()
             ))?;
             out.write_fmt(format_args!(
                 "         triggers ({} {} {} {} {} {})\n",
-                mv.triggers[0] as u8,
-                mv.triggers[1] as u8,
-                mv.triggers[2] as u8,
-                mv.triggers[3] as u8,
-                mv.triggers[4] as u8,
-                mv.triggers[5] as u8
+                mv.triggers.pressed(Trigger::Fire) as u8,
+                mv.triggers.pressed(Trigger::AltFire) as u8,
+                mv.triggers.pressed(Trigger::Jump) as u8,
+                mv.triggers.pressed(Trigger::Trigger3) as u8,
+                mv.triggers.pressed(Trigger::Trigger4) as u8,
+                mv.triggers.pressed(Trigger::Trigger5) as u8
             ))?;
         }
         out.write_fmt(format_args!("      }}\n"))?;
@@ -96,7 +102,8 @@ impl TasFile {
         &self,
         seq: &Sequence,
         out: &mut T,
-        elapsed: &mut u32,
+        timeline: &Timeline,
+        first: usize,
     ) -> Result<()>
     where
         T: Write,
@@ -109,8 +116,8 @@ impl TasFile {
         let mut i = 0;
         while i < seq.frames.len() {
             let frame = &seq.frames[i];
+            let elapsed = timeline.end_of(first + i).unwrap_or(0);
 
-            *elapsed += u32::from(frame.delta);
             if frame.has_move() {
                 out.write_fmt(format_args!(
                     "      moveframe {} ms //{}\n",
@@ -137,9 +144,8 @@ impl TasFile {
                         combined,
                         frame.delta,
                         elapsed,
-                        *elapsed + u32::from(frame.delta) * (combined - 1) as u32
+                        timeline.end_of(first + i).unwrap_or(0)
                     ))?;
-                    *elapsed += u32::from(frame.delta) * (combined - 1) as u32;
                 } else {
                     out.write_fmt(format_args!(
                         "      frame {} ms // {}\n",
@@ -161,10 +167,12 @@ impl TasFile {
     {
         out.write_fmt(format_args!("{{\n   {}\n", TasFile::escape(&self.mission)))?;
 
-        let mut elapsed: u32 = 0;
+        let timeline = Timeline::new(self.sequences.iter().flat_map(|seq| &seq.frames));
 
+        let mut first = 0;
         for seq in &self.sequences {
-            self.print_sequence(seq, out, &mut elapsed)?;
+            self.print_sequence(seq, out, &timeline, first)?;
+            first += seq.frames.len();
         }
         out.write_fmt(format_args!("}}\n"))?;
         Ok(())
@@ -293,25 +301,32 @@ fn bool6<'a, E: ParseError<&'a str>>(
 }
 
 fn move_inner<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Move, E> {
-    ws_wrap(map(
-        tuple((
-            preceded(tag("camera"), ws_wrap(float3)),
-            preceded(tag("move"), ws_wrap(float3)),
-            preceded(tag("triggers"), ws_wrap(bool6)),
-        )),
-        |((yaw, pitch, roll), (mx, my, mz), triggers)| Move {
-            yaw: Some(yaw),
-            pitch: Some(pitch),
-            roll: Some(roll),
-            mx,
-            my,
-            mz,
-            freelook: true,
-            triggers: [
-                triggers.0, triggers.1, triggers.2, triggers.3, triggers.4, triggers.5,
-            ],
-        },
-    ))(i)
+    let (rest, ((yaw, pitch, roll), (mx, my, mz), triggers)) = ws_wrap(tuple((
+        preceded(tag("camera"), ws_wrap(float3)),
+        preceded(tag("move"), ws_wrap(float3)),
+        preceded(tag("triggers"), ws_wrap(bool6)),
+    )))(i)?;
+    let values = MoveValues {
+        yaw: Some(yaw),
+        pitch: Some(pitch),
+        roll: Some(roll),
+        mx,
+        my,
+        mz,
+        freelook: true,
+        triggers: Triggers::from([
+            triggers.0, triggers.1, triggers.2, triggers.3, triggers.4, triggers.5,
+        ]),
+    };
+    // Values a rec can't hold fail here, rather than as a missing '}'
+    match values.quantize(Quantization::default()) {
+        Ok((mv, _)) => Ok((rest, mv)),
+        Err(_) => Err(Err::Failure(E::add_context(
+            i,
+            "move values",
+            E::from_error_kind(i, nom::error::ErrorKind::MapRes),
+        ))),
+    }
 }
 
 fn move_<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Option<Move>, E> {
@@ -0,0 +1,158 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+use serde::Serialize;
+use crate::recording::{Frame, Recording};
+
+// When each frame happens, for going between game time and frame indices
+// without adding up deltas every time. Times are in ms from the start of the
+// rec, and frame i runs from `time_of(i)` until `time_of(i + 1)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Timeline {
+    // Time each frame starts at, and then the time the last one ends at
+    starts: Vec<u64>,
+}
+
+impl Timeline {
+    pub fn new<'a, I: IntoIterator<Item = &'a Frame>>(frames: I) -> Timeline {
+        let frames = frames.into_iter();
+        let mut starts = Vec::with_capacity(frames.size_hint().0 + 1);
+        let mut elapsed = 0u64;
+        starts.push(elapsed);
+        for frame in frames {
+            elapsed += u64::from(frame.delta);
+            starts.push(elapsed);
+        }
+        Timeline { starts }
+    }
+
+    // Number of frames
+    pub fn len(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn duration(&self) -> u64 {
+        self.starts[self.len()]
+    }
+
+    // When `frame` starts. One past the last frame is when the rec ends.
+    pub fn time_of(&self, frame: usize) -> Option<u64> {
+        self.starts.get(frame).copied()
+    }
+
+    // When `frame` ends, which is also the time the game shows once it's done
+    pub fn end_of(&self, frame: usize) -> Option<u64> {
+        if frame < self.len() {
+            self.time_of(frame + 1)
+        } else {
+            None
+        }
+    }
+
+    // The frame running at `ms`, None once the rec is over. Frames with a 0
+    // delta never run at any time, the one after them does.
+    pub fn frame_at(&self, ms: u64) -> Option<usize> {
+        if ms >= self.duration() {
+            return None;
+        }
+        Some(self.first_from(ms.saturating_add(1)) - 1)
+    }
+
+    // Frames that start from `range.start` until `range.end`, the frames an
+    // edit of that range covers
+    pub fn frames_in(&self, range: Range<u64>) -> Range<usize> {
+        let start = self.first_from(range.start);
+        start..self.first_from(range.end).max(start)
+    }
+
+    // Index of the first frame starting at or after `ms`, or the number of
+    // frames if there are none
+    fn first_from(&self, ms: u64) -> usize {
+        self.starts[..self.len()].partition_point(|&start| start < ms)
+    }
+}
+
+impl Recording {
+    pub fn timeline(&self) -> Timeline {
+        Timeline::new(&self.frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeline(deltas: &[u16]) -> Timeline {
+        let frames: Vec<Frame> = deltas.iter().map(|&delta| Frame { moves: [None, None], delta }).collect();
+        Timeline::new(&frames)
+    }
+
+    #[test]
+    fn frame_at_frame_boundaries() {
+        let timeline = timeline(&[16, 17, 10]);
+        assert_eq!(timeline.frame_at(0), Some(0));
+        assert_eq!(timeline.frame_at(15), Some(0));
+        assert_eq!(timeline.frame_at(16), Some(1));
+        assert_eq!(timeline.frame_at(32), Some(1));
+        assert_eq!(timeline.frame_at(33), Some(2));
+        assert_eq!(timeline.frame_at(42), Some(2));
+        for frame in 0..timeline.len() {
+            let start = timeline.time_of(frame).unwrap();
+            assert_eq!(timeline.frame_at(start), Some(frame));
+            assert_eq!(timeline.end_of(frame), timeline.time_of(frame + 1));
+        }
+    }
+
+    #[test]
+    fn zero_ms_frames_never_run() {
+        let timeline = timeline(&[0, 16, 0, 0, 17, 0]);
+        assert_eq!(timeline.len(), 6);
+        assert_eq!(timeline.frame_at(0), Some(1));
+        assert_eq!(timeline.frame_at(15), Some(1));
+        assert_eq!(timeline.frame_at(16), Some(4));
+        assert_eq!(timeline.frame_at(32), Some(4));
+        // They still have a time
+        assert_eq!(timeline.time_of(0), Some(0));
+        assert_eq!(timeline.time_of(2), Some(16));
+        assert_eq!(timeline.time_of(3), Some(16));
+        assert_eq!(timeline.end_of(3), Some(16));
+        assert_eq!(timeline.time_of(5), Some(33));
+        assert_eq!(timeline.end_of(5), Some(33));
+        // And are covered by ranges that start where they are
+        assert_eq!(timeline.frames_in(16..17), 2..5);
+        assert_eq!(timeline.frames_in(0..16), 0..2);
+        assert_eq!(timeline.frames_in(33..40), 5..6);
+    }
+
+    #[test]
+    fn past_the_end() {
+        let timeline = timeline(&[16, 17, 10]);
+        assert_eq!(timeline.duration(), 43);
+        assert_eq!(timeline.frame_at(43), None);
+        assert_eq!(timeline.frame_at(u64::MAX), None);
+        // One past the last frame is the end of the rec, past that is nothing
+        assert_eq!(timeline.time_of(3), Some(43));
+        assert_eq!(timeline.time_of(4), None);
+        assert_eq!(timeline.end_of(2), Some(43));
+        assert_eq!(timeline.end_of(3), None);
+        assert_eq!(timeline.frames_in(40..1000), 3..3);
+        assert_eq!(timeline.frames_in(0..1000), 0..3);
+        // Backwards ranges are empty
+        let (start, end) = (20, 10);
+        assert_eq!(timeline.frames_in(start..end), 2..2);
+    }
+
+    #[test]
+    fn empty_timeline() {
+        let timeline = timeline(&[]);
+        assert!(timeline.is_empty());
+        assert_eq!(timeline.duration(), 0);
+        assert_eq!(timeline.frame_at(0), None);
+        assert_eq!(timeline.time_of(0), Some(0));
+        assert_eq!(timeline.end_of(0), None);
+        assert_eq!(timeline.frames_in(0..10), 0..0);
+    }
+}
@@ -0,0 +1,501 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+use serde::Serialize;
+use crate::quantize::Quantization;
+use crate::recording::{Angle, Axis, Frame, Framing, Move, MoveError, MoveValues, RawBits, Recording, TICK_MS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Severity {
+    // Fine, but not how the game would have written it
+    Info,
+    // Odd enough that the rec might not play back the way it was recorded,
+    // including values the game could never have recorded but that still
+    // have bits they can be written as
+    Warning,
+    // Can't be written at all, or the game can't load it
+    Error,
+}
+
+impl Severity {
+    pub fn is_error(&self) -> bool {
+        *self == Severity::Error
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum IssueKind {
+    ZeroDelta,
+    DeltaTooLong,
+    NotFinite,
+    AxisOutOfRange,
+    AxisOffGrid,
+    AngleOutOfRange,
+    AngleOffGrid,
+    EmptyMission,
+    AbsoluteMission,
+    MissingMoves,
+    MissionEncoding,
+    Framing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    // None for issues with the rec as a whole
+    pub frame: Option<usize>,
+    pub message: String,
+}
+
+impl Issue {
+    fn new(severity: Severity, kind: IssueKind, frame: Option<usize>, message: String) -> Issue {
+        Issue {
+            severity,
+            kind,
+            frame,
+            message,
+        }
+    }
+}
+
+impl Recording {
+    // Everything that looks wrong with the rec, beyond it having parsed. Recs
+    // read from a file can't have some of these (an 11 bit delta, say), but
+    // ones built by hand or imported from JSON or TAS files can.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = vec![];
+        self.validate_mission(&mut issues);
+
+        // Loading frames at the start don't get moves however long they take
+        let mut loading = true;
+        for (i, frame) in self.frames.iter().enumerate() {
+            validate_delta(&mut issues, i, frame);
+            if frame.has_move() {
+                loading = false;
+            } else if !loading && frame.delta >= TICK_MS {
+                issues.push(Issue::new(
+                    Severity::Warning,
+                    IssueKind::MissingMoves,
+                    Some(i),
+                    format!("Frame takes {} ms but has no moves, a tick should have run", frame.delta),
+                ));
+            }
+            for (slot, mv) in frame.moves.iter().enumerate() {
+                if let Some(mv) = mv {
+                    validate_move(&mut issues, i, slot, mv);
+                }
+            }
+        }
+
+        if let Some(framing) = &self.framing {
+            validate_framing(&mut issues, &self.frames, framing);
+        }
+        issues
+    }
+
+    fn validate_mission(&self, issues: &mut Vec<Issue>) {
+        let mission = self.mission.trim();
+        if mission.is_empty() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::EmptyMission,
+                None,
+                "Mission path is empty".to_string(),
+            ));
+        } else if is_absolute(mission) {
+            issues.push(Issue::new(
+                Severity::Warning,
+                IssueKind::AbsoluteMission,
+                None,
+                format!("Mission path {:?} is absolute, the game uses paths like marble/data/missions/...", mission),
+            ));
+        }
+        if !self.mission_encoding.is_utf8() {
+            issues.push(Issue::new(
+                Severity::Info,
+                IssueKind::MissionEncoding,
+                None,
+                format!("Mission path is stored as {:?}", self.mission_encoding),
+            ));
+        }
+    }
+}
+
+impl MoveValues {
+    // What quantizing these into a move would lose or not manage, for TAS
+    // tools to warn about before it happens. `frame` and `slot` only go in
+    // the issues.
+    pub fn validate(&self, frame: usize, slot: usize) -> Vec<Issue> {
+        let mut issues = vec![];
+        validate_values(&mut issues, frame, slot, self);
+        issues
+    }
+}
+
+// Whether any of the issues should stop a rec from being written
+pub fn has_errors(issues: &[Issue]) -> bool {
+    issues.iter().any(|issue| issue.severity.is_error())
+}
+
+// Unix, Windows drive and UNC paths
+fn is_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with('/')
+        || path.starts_with('\\')
+        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+fn validate_delta(issues: &mut Vec<Issue>, i: usize, frame: &Frame) {
+    if frame.delta == 0 {
+        issues.push(Issue::new(
+            Severity::Warning,
+            IssueKind::ZeroDelta,
+            Some(i),
+            "Frame takes 0 ms".to_string(),
+        ));
+    } else if frame.delta > Frame::MAX_DELTA {
+        issues.push(Issue::new(
+            Severity::Error,
+            IssueKind::DeltaTooLong,
+            Some(i),
+            format!("Frame delta {} ms does not fit in 10 bits (max {})", frame.delta, Frame::MAX_DELTA),
+        ));
+    }
+}
+
+// The steps a move holds always decode to an angle, but an axis from JSON
+// can have more bits than get written
+fn validate_move(issues: &mut Vec<Issue>, i: usize, slot: usize, mv: &Move) {
+    let axes = [("mx", mv.mx), ("my", mv.my), ("mz", mv.mz)];
+    for (name, axis) in axes.iter() {
+        if axis.0 >> Axis::BITS != 0 {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::AxisOutOfRange,
+                Some(i),
+                format!("Move {} {} step {} does not fit in {} bits", slot, name, axis.0, Axis::BITS),
+            ));
+        } else if axis.to_f64() > -Axis::MIN {
+            // Still writes fine, the game just could never have recorded it
+            issues.push(Issue::new(
+                Severity::Warning,
+                IssueKind::AxisOutOfRange,
+                Some(i),
+                format!("Move {} {} {} is outside [{}, {}], the game's input never goes that far", slot, name, axis.to_f64(), Axis::MIN, -Axis::MIN),
+            ));
+        }
+    }
+}
+
+// A move as floats, before it is quantized
+fn validate_values(issues: &mut Vec<Issue>, i: usize, slot: usize, values: &MoveValues) {
+    let policy = Quantization::default();
+    let angles = [("yaw", values.yaw), ("pitch", values.pitch), ("roll", values.roll)];
+    for (name, angle) in angles.iter() {
+        let angle = match angle {
+            Some(angle) => *angle,
+            None => continue,
+        };
+        if !angle.is_finite() {
+            issues.push(not_finite(i, slot, name, angle));
+        } else if Angle::from_f64(angle, policy).is_err() {
+            // Writing only wraps negative angles up by one turn
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::AngleOutOfRange,
+                Some(i),
+                format!("Move {} {} {} is outside [-2pi, 2pi) and can't be written", slot, name, angle),
+            ));
+        } else if !(-PI..PI).contains(&angle) {
+            issues.push(Issue::new(
+                Severity::Warning,
+                IssueKind::AngleOutOfRange,
+                Some(i),
+                format!("Move {} {} {} is outside [-pi, pi) and will be wrapped", slot, name, angle),
+            ));
+        } else {
+            // Same wrapping and rounding as writing does
+            let error = Angle::from_f64(angle, policy).map(|(_, error)| error).unwrap_or(0f64);
+            if error.abs() >= MoveError::TOLERANCE {
+                issues.push(Issue::new(
+                    Severity::Warning,
+                    IssueKind::AngleOffGrid,
+                    Some(i),
+                    format!("Move {} {} {} will be written {} off", slot, name, angle, error),
+                ));
+            }
+        }
+    }
+
+    let axes = [("mx", values.mx), ("my", values.my), ("mz", values.mz)];
+    for (name, axis) in axes.iter() {
+        let axis = *axis;
+        if !axis.is_finite() {
+            issues.push(not_finite(i, slot, name, axis));
+        } else if Axis::from_f64(axis, policy).is_err() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::AxisOutOfRange,
+                Some(i),
+                format!("Move {} {} {} does not fit in [{}, {}]", slot, name, axis, Axis::MIN, Axis::MAX),
+            ));
+        } else if !(Axis::MIN..=-Axis::MIN).contains(&axis) {
+            // Like an angle past pi this still writes fine, the game just
+            // could never have recorded it
+            issues.push(Issue::new(
+                Severity::Warning,
+                IssueKind::AxisOutOfRange,
+                Some(i),
+                format!("Move {} {} {} is outside [{}, {}], the game's input never goes that far", slot, name, axis, Axis::MIN, -Axis::MIN),
+            ));
+        } else {
+            let error = Axis::from_f64(axis, policy).map(|(_, error)| error).unwrap_or(0f64);
+            if error.abs() >= MoveError::TOLERANCE {
+                issues.push(Issue::new(
+                    Severity::Warning,
+                    IssueKind::AxisOffGrid,
+                    Some(i),
+                    format!("Move {} {} {} is not a multiple of 1/16 and will be written {} off", slot, name, axis, error),
+                ));
+            }
+        }
+    }
+}
+
+fn not_finite(i: usize, slot: usize, name: &str, value: f64) -> Issue {
+    Issue::new(
+        Severity::Error,
+        IssueKind::NotFinite,
+        Some(i),
+        format!("Move {} {} is {}", slot, name, value),
+    )
+}
+
+// Blocks from a lossless read that the game would have laid out differently
+fn validate_framing(issues: &mut Vec<Issue>, frames: &[Frame], framing: &Framing) {
+    for (i, (frame, padding)) in frames.iter().zip(framing.padding.iter()).enumerate() {
+        let game_bits = match RawBits::game_padding(frame) {
+            Some(game) => game.len_bits,
+            // Already reported as a problem with the frame itself
+            None => continue,
+        };
+        let message = if padding.len_bits != game_bits {
+            format!(
+                "Block has {} bits of padding after the frame where the game writes {}",
+                padding.len_bits, game_bits
+            )
+        } else if padding.bytes.iter().any(|&b| b != 0) {
+            "Block padding after the frame is not all zeroes".to_string()
+        } else {
+            continue;
+        };
+        issues.push(Issue::new(Severity::Info, IssueKind::Framing, Some(i), message));
+    }
+    if !framing.trailing.is_empty() {
+        issues.push(Issue::new(
+            Severity::Info,
+            IssueKind::Framing,
+            None,
+            format!("Rec has {} bits after the last block", framing.trailing.len_bits),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::Encoding;
+    use crate::recording::Triggers;
+
+    fn values(mx: f64) -> MoveValues {
+        MoveValues {
+            yaw: None,
+            pitch: None,
+            roll: None,
+            mx,
+            my: 0f64,
+            mz: 0f64,
+            freelook: false,
+            triggers: Triggers::default(),
+        }
+    }
+
+    fn frame(delta: u16, moved: bool) -> Frame {
+        let mv = values(0f64).quantize(Quantization::default()).unwrap().0;
+        Frame {
+            moves: [if moved { Some(mv) } else { None }, None],
+            delta,
+        }
+    }
+
+    fn rec(frames: Vec<Frame>) -> Recording {
+        Recording {
+            mission: "marble/data/missions/beginner/movement.mis".to_string(),
+            frames,
+            mission_encoding: Encoding::Utf8,
+            framing: None,
+            metadata: None,
+        }
+    }
+
+    fn found(rec: &Recording) -> Vec<(IssueKind, Severity, Option<usize>)> {
+        rec.validate().iter().map(|issue| (issue.kind, issue.severity, issue.frame)).collect()
+    }
+
+    fn axis_issue(mx: f64) -> Option<Severity> {
+        values(mx).validate(0, 0).first().map(|issue| issue.severity)
+    }
+
+    fn angle_issue(yaw: f64) -> Option<(IssueKind, Severity)> {
+        let values = MoveValues {
+            yaw: Some(yaw),
+            ..values(0f64)
+        };
+        values.validate(0, 0).first().map(|issue| (issue.kind, issue.severity))
+    }
+
+    #[test]
+    fn a_plain_rec_has_no_issues() {
+        let frames = (0..10).map(|i| frame(16 + i % 2, true)).collect();
+        assert_eq!(found(&rec(frames)), []);
+    }
+
+    #[test]
+    fn bad_deltas() {
+        let rec = rec(vec![frame(16, true), frame(0, true), frame(Frame::MAX_DELTA, true), frame(Frame::MAX_DELTA + 1, true)]);
+        assert_eq!(
+            found(&rec),
+            [
+                (IssueKind::ZeroDelta, Severity::Warning, Some(1)),
+                (IssueKind::DeltaTooLong, Severity::Error, Some(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn mission_paths() {
+        let mission = |mission: &str| {
+            let mut rec = rec(vec![frame(16, true)]);
+            rec.mission = mission.to_string();
+            found(&rec)
+        };
+        assert_eq!(mission("marble/data/missions/intermediate/gravity.mis"), []);
+        assert_eq!(mission("  "), [(IssueKind::EmptyMission, Severity::Error, None)]);
+        for &path in &["/home/me/marble/data/missions/a.mis", "C:\\Marble\\a.mis", "\\\\server\\a.mis"] {
+            assert_eq!(mission(path), [(IssueKind::AbsoluteMission, Severity::Warning, None)], "{}", path);
+        }
+
+        let mut latin1 = rec(vec![frame(16, true)]);
+        latin1.mission_encoding = Encoding::Latin1;
+        assert_eq!(found(&latin1), [(IssueKind::MissionEncoding, Severity::Info, None)]);
+    }
+
+    #[test]
+    fn missing_moves_only_after_loading() {
+        let rec = rec(vec![
+            // Loading, however long it takes
+            frame(200, false),
+            frame(TICK_MS, false),
+            frame(16, true),
+            // Too short for a tick to have run
+            frame(TICK_MS - 1, false),
+            frame(TICK_MS, false),
+            frame(16, true),
+        ]);
+        assert_eq!(found(&rec), [(IssueKind::MissingMoves, Severity::Warning, Some(4))]);
+    }
+
+    #[test]
+    fn framing_the_game_would_not_write() {
+        // Frames without moves, so there's padding to play with
+        let frames = vec![frame(16, true), frame(16, false), frame(16, false)];
+        let game = |frame: &Frame| RawBits::game_padding(frame).unwrap();
+        let mut junk = game(&frames[1]);
+        junk.bytes[0] = 0x5A;
+        let mut long = game(&frames[2]);
+        long.bytes.push(0);
+        long.len_bits += 8;
+
+        let mut rec = rec(frames);
+        rec.framing = Some(Framing {
+            padding: vec![game(&rec.frames[0]), junk, long],
+            terminated: true,
+            trailing: RawBits::default(),
+        });
+        assert_eq!(
+            found(&rec),
+            [
+                (IssueKind::Framing, Severity::Info, Some(1)),
+                (IssueKind::Framing, Severity::Info, Some(2)),
+            ]
+        );
+
+        rec.framing.as_mut().unwrap().padding = rec.frames.iter().map(game).collect();
+        rec.framing.as_mut().unwrap().trailing = RawBits {
+            bytes: vec![0xFF],
+            len_bits: 3,
+        };
+        assert_eq!(found(&rec), [(IssueKind::Framing, Severity::Info, None)]);
+    }
+
+    #[test]
+    fn angle_severity_follows_what_can_be_written() {
+        let step = 2f64 * PI / 65536f64;
+        assert_eq!(angle_issue(0f64), None);
+        assert_eq!(angle_issue(Angle(12345).to_f64()), None);
+        assert_eq!(angle_issue(-PI), None);
+        assert_eq!(angle_issue(step * 2.5), Some((IssueKind::AngleOffGrid, Severity::Warning)));
+        // Wrapped, but written fine
+        assert_eq!(angle_issue(PI), Some((IssueKind::AngleOutOfRange, Severity::Warning)));
+        assert_eq!(angle_issue(-PI - 0.5), Some((IssueKind::AngleOutOfRange, Severity::Warning)));
+        assert_eq!(angle_issue(2f64 * PI - step), Some((IssueKind::AngleOutOfRange, Severity::Warning)));
+        // Rounds to a whole turn, which writes as 0
+        assert_eq!(angle_issue(2f64 * PI), Some((IssueKind::AngleOutOfRange, Severity::Warning)));
+        // What writing rejects
+        assert_eq!(angle_issue(2f64 * PI + step), Some((IssueKind::AngleOutOfRange, Severity::Error)));
+        assert_eq!(angle_issue(7f64), Some((IssueKind::AngleOutOfRange, Severity::Error)));
+        assert_eq!(angle_issue(-2f64 * PI - 0.5), Some((IssueKind::AngleOutOfRange, Severity::Error)));
+        assert_eq!(angle_issue(f64::NAN), Some((IssueKind::NotFinite, Severity::Error)));
+        for &yaw in &[2f64 * PI + step, 7f64, -2f64 * PI - 0.5] {
+            let values = MoveValues {
+                yaw: Some(yaw),
+                ..values(0f64)
+            };
+            assert!(values.quantize(Quantization::default()).is_err(), "{}", yaw);
+        }
+    }
+
+    #[test]
+    fn axis_severity_follows_what_can_be_written() {
+        assert_eq!(axis_issue(1f64), None);
+        assert_eq!(axis_issue(-1f64), None);
+        // Past what the game records, but there are bits for it
+        assert_eq!(axis_issue(1.0625), Some(Severity::Warning));
+        assert_eq!(axis_issue(Axis::MAX), Some(Severity::Warning));
+        assert_eq!(axis_issue(Axis::MAX + Axis::SCALE), Some(Severity::Error));
+        assert_eq!(axis_issue(Axis::MIN - Axis::SCALE), Some(Severity::Error));
+        assert_eq!(axis_issue(0.1), Some(Severity::Warning));
+    }
+
+    #[test]
+    fn axis_steps_past_six_bits_are_errors() {
+        let (mut mv, _) = values(0f64).quantize(Quantization::default()).unwrap();
+        let severity = |mv: &Move| {
+            let mut issues = vec![];
+            validate_move(&mut issues, 0, 0, mv);
+            issues.first().map(|issue| issue.severity)
+        };
+        assert_eq!(severity(&mv), None);
+        mv.mx = Axis(33);
+        assert_eq!(severity(&mv), Some(Severity::Warning));
+        mv.mx = Axis(64);
+        assert_eq!(severity(&mv), Some(Severity::Error));
+
+        let mut rec = rec(vec![frame(16, true)]);
+        rec.frames[0].moves[0] = Some(mv);
+        assert_eq!(found(&rec), [(IssueKind::AxisOutOfRange, Severity::Error, Some(0))]);
+    }
+}
@@ -0,0 +1,111 @@
+use wasm_bindgen::prelude::*;
+use crate::rec_format::{read_any, write_any};
+use crate::recording::{ReadOptions, Recording, WriteOptions};
+use crate::tas_rec::TasFile;
+use crate::error::Result;
+use crate::error::ErrorKind::GenericError2;
+use crate::validate::has_errors;
+
+#[wasm_bindgen]
+extern {
+    fn alert(s: &str);
+}
+
+#[wasm_bindgen]
+pub fn import_rec(conts: Vec<u8>) -> Option<String> {
+    if let Ok(result) = import_opt(conts, &ReadOptions::default()) {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+// import_rec, but `lossless` keeps the block layout, so export_rec gives back
+// the exact same bytes
+#[wasm_bindgen]
+pub fn import_rec_with(conts: Vec<u8>, lossless: bool) -> Option<String> {
+    let options = ReadOptions {
+        lossless,
+        ..ReadOptions::default()
+    };
+    import_opt(conts, &options).ok()
+}
+
+fn import_opt(conts: Vec<u8>, options: &ReadOptions) -> Result<String> {
+    let r = read_any(&conts, options)?;
+    let tf = serde_json::to_string(&r)?;
+    Ok(tf)
+}
+
+// Stats for the rec as JSON, see stats::Stats
+#[wasm_bindgen]
+pub fn rec_stats(conts: Vec<u8>) -> Option<String> {
+    stats_opt(conts).ok()
+}
+
+fn stats_opt(conts: Vec<u8>) -> Result<String> {
+    let r = read_any(&conts, &ReadOptions::default())?;
+    Ok(serde_json::to_string(&r.stats())?)
+}
+
+// When every frame starts, and then when the last one ends, as JSON
+#[wasm_bindgen]
+pub fn rec_timeline(conts: Vec<u8>) -> Option<String> {
+    timeline_opt(conts).ok()
+}
+
+fn timeline_opt(conts: Vec<u8>) -> Result<String> {
+    let r = read_any(&conts, &ReadOptions::default())?;
+    Ok(serde_json::to_string(&r.timeline())?)
+}
+
+// Where the camera points after every move, as JSON, see camera::CameraPath
+#[wasm_bindgen]
+pub fn rec_camera(conts: Vec<u8>) -> Option<String> {
+    camera_opt(conts).ok()
+}
+
+fn camera_opt(conts: Vec<u8>) -> Result<String> {
+    let r = read_any(&conts, &ReadOptions::default())?;
+    Ok(serde_json::to_string(&r.camera_path())?)
+}
+
+#[wasm_bindgen]
+pub fn export_rec(input: String) -> Vec<u8> {
+    match export_opt(input) {
+        Ok(mut result) => {
+            result.insert(0, 1);
+            result
+        }
+        Err(error) => {
+            let mut result = format!("{:?}", error).into_bytes();
+            result.insert(0, 0);
+            result
+        }
+    }
+}
+
+fn export_opt(input: String) -> Result<Vec<u8>> {
+    let r = if let Ok(r) = serde_json::from_str::<Recording>(&input) {
+        r
+    } else {
+        let tf = TasFile::parse(input)?;
+        tf.into_rec()
+    };
+
+    let issues = r.validate();
+    if has_errors(&issues) {
+        let messages = issues
+            .iter()
+            .filter(|issue| issue.severity.is_error())
+            .map(|issue| match issue.frame {
+                Some(frame) => format!("Frame {}: {}", frame, issue.message),
+                None => issue.message.clone(),
+            })
+            .collect::<Vec<_>>();
+        return Err(GenericError2(messages.join("\n")).into());
+    }
+
+    write_any(r, &WriteOptions::default())
+}
+
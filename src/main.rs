@@ -6,8 +6,13 @@ use std::path::{Path, PathBuf};
 use std::process::{exit, Command};
 use std::ffi::OsString;
 use std::fmt::Display;
-use librec::recording::Recording;
-use librec::bit_stream::BitStream;
+use librec::rec_format::read_any_from;
+use librec::recording::{ReadOptions, Recording, Trigger};
+use librec::diff::diff;
+use librec::dissect::dissect;
+use librec::salvage::salvage;
+use librec::stats::Stats;
+use librec::validate::has_errors;
 
 fn dir_parents(dir: &Path) -> Vec<&Path> {
     match dir.parent() {
@@ -83,9 +88,233 @@ fn format_time(mut t: i32) -> String {
     format!("{:02}:{:02}.{:03}", (t / 1000) / 60, (t / 1000) % 60, t % 1000)
 }
 
+// Streams a rec in for the subcommands, a file that can't be read is told
+// apart from one that isn't a rec
+fn load_rec(path: &str) -> Result<Recording, Error> {
+    let recording = read_any_from(File::open(path)?, &ReadOptions::default()).unwrap_or_else(|e| {
+        if e.is_io() {
+            eprintln!("Failed to read rec file {}: {}", path, e.display_chain());
+        } else {
+            eprintln!("Failed to load rec file {}: {}", path, e.display_chain());
+        }
+        exit(-1);
+    });
+    Ok(recording)
+}
+
+// recverify dissect <rec> [--json]
+fn run_dissect(argv: &[String]) -> Result<(), Error> {
+    let src_path = match argv.iter().find(|arg| !arg.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: recverify dissect <rec file> [--json]");
+            exit(-1);
+        }
+    };
+
+    let dissection = dissect(&fs::read(src_path)?);
+    if argv.iter().any(|arg| arg == "--json") {
+        match dissection.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to write json: {}", e);
+                exit(-1);
+            }
+        }
+    } else {
+        print!("{}", dissection.hexdump());
+    }
+
+    if dissection.error.is_some() {
+        exit(1);
+    }
+    Ok(())
+}
+
+// recverify repair <rec> [output rec] [--force]
+fn run_repair(argv: &[String]) -> Result<(), Error> {
+    let paths = argv.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+    let force = argv.iter().any(|arg| arg == "--force");
+    let src_path = match paths.first() {
+        Some(path) => *path,
+        None => {
+            eprintln!("Usage: recverify repair <rec file> [output rec file] [--force]");
+            exit(-1);
+        }
+    };
+    let dest_path = match paths.get(1) {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(src_path).with_extension("repaired.rec"),
+    };
+
+    let salvage = salvage(&fs::read(src_path)?).unwrap_or_else(|e| {
+        eprintln!("Failed to read rec mission: {}", e);
+        exit(-1);
+    });
+    let timeline = salvage.recording.timeline();
+    for damage in &salvage.damage {
+        let time = timeline.time_of(damage.frame).unwrap_or(0);
+        println!(
+            "Damaged at byte {} before frame {} at {} ({} bytes skipped): {}",
+            damage.offset, damage.frame, format_time(time as i32), damage.length, damage.reason
+        );
+    }
+    println!("Recovered {} frames", salvage.recording.frames.len());
+
+    let issues = salvage.recording.validate();
+    for issue in &issues {
+        match issue.frame {
+            Some(frame) => println!("{:?} in frame {}: {}", issue.severity, frame, issue.message),
+            None => println!("{:?}: {}", issue.severity, issue.message),
+        }
+    }
+    if has_errors(&issues) && !force {
+        eprintln!("Not writing a rec with errors, pass --force to write it anyway");
+        exit(1);
+    }
+
+    let bytes = salvage.recording.into_bytes().unwrap_or_else(|e| {
+        eprintln!("Failed to write rec: {}", e);
+        exit(-1);
+    });
+    fs::write(&dest_path, bytes)?;
+    println!("Wrote {}", dest_path.to_str().unwrap_or("<cannot display path>"));
+    Ok(())
+}
+
+// recverify diff <a rec> <b rec> [--json]
+fn run_diff(argv: &[String]) -> Result<(), Error> {
+    let paths = argv.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+    if paths.len() != 2 {
+        eprintln!("Usage: recverify diff <rec file> <other rec file> [--json]");
+        exit(-1);
+    }
+
+    let mut recordings = vec![];
+    for path in paths {
+        recordings.push(load_rec(path)?);
+    }
+
+    let difference = diff(&recordings[0], &recordings[1]);
+    if argv.iter().any(|arg| arg == "--json") {
+        match difference.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to write json: {}", e);
+                exit(-1);
+            }
+        }
+    } else {
+        print!("{}", difference.report());
+    }
+
+    if !difference.is_same() {
+        exit(1);
+    }
+    Ok(())
+}
+
+// recverify camera <rec> [--json]
+fn run_camera(argv: &[String]) -> Result<(), Error> {
+    let src_path = match argv.iter().find(|arg| !arg.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: recverify camera <rec file> [--json]");
+            exit(-1);
+        }
+    };
+
+    let recording = load_rec(src_path)?;
+    let path = recording.camera_path();
+    if argv.iter().any(|arg| arg == "--json") {
+        match path.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to write json: {}", e);
+                exit(-1);
+            }
+        }
+    } else {
+        print!("{}", path.to_csv());
+    }
+    Ok(())
+}
+
+fn format_fps(stats: &Stats) -> String {
+    match stats.fps {
+        Some(fps) => format!("{:.2}", fps),
+        None => "N/A".to_string(),
+    }
+}
+
+// recverify info <rec> [--json]
+fn run_info(argv: &[String]) -> Result<(), Error> {
+    let src_path = match argv.iter().find(|arg| !arg.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: recverify info <rec file> [--json]");
+            exit(-1);
+        }
+    };
+
+    let recording = load_rec(src_path)?;
+    let stats = recording.stats();
+    if argv.iter().any(|arg| arg == "--json") {
+        match stats.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to write json: {}", e);
+                exit(-1);
+            }
+        }
+        return Ok(());
+    }
+
+    println!("MISSION: {}", recording.mission);
+    println!("FRAMES: {}", stats.frames);
+    println!("DURATION: {} ({})", stats.duration, format_time(stats.duration as i32));
+    println!("APPROXIMATE FPS: {}", format_fps(&stats));
+    if let Some(times) = stats.frame_times {
+        println!(
+            "FRAME TIMES: min {} / p50 {} / p90 {} / p99 {} / max {} ms",
+            times.min, times.p50, times.p90, times.p99, times.max
+        );
+    }
+    println!("INPUT: {} frames ({:.1}%)", stats.input_frames, stats.input_share * 100f64);
+    for (trigger, stats) in Trigger::ALL.iter().zip(stats.triggers.iter()) {
+        if stats.presses > 0 {
+            println!(
+                "{}: {} presses, held {} ms (longest {} ms)",
+                trigger.name().to_uppercase(), stats.presses, stats.held_ms, stats.longest_ms
+            );
+        }
+    }
+    println!("DELTAS:");
+    for (delta, count) in &stats.deltas {
+        println!("{:>6} ms: {}", delta, count);
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Error> {
     let argv = args().collect::<Vec<_>>();
 
+    if argv.get(1).map(|s| s.as_str()) == Some("dissect") {
+        return run_dissect(&argv[2..]);
+    }
+    if argv.get(1).map(|s| s.as_str()) == Some("repair") {
+        return run_repair(&argv[2..]);
+    }
+    if argv.get(1).map(|s| s.as_str()) == Some("info") {
+        return run_info(&argv[2..]);
+    }
+    if argv.get(1).map(|s| s.as_str()) == Some("diff") {
+        return run_diff(&argv[2..]);
+    }
+    if argv.get(1).map(|s| s.as_str()) == Some("camera") {
+        return run_camera(&argv[2..]);
+    }
+
     if argv.len() < 2 {
         let main_exe = argv
             .first()
@@ -109,8 +338,13 @@ fn main() -> Result<(), Error> {
 
     for src_path in &argv[1..] {
         // Load rec file
-        let mut bit_stream = BitStream::new(fs::read(src_path)?);
-        let recording = Recording::from_stream(&mut bit_stream).unwrap_or_else(|_| terminate_with_error("Failed to load rec file"));
+        let recording = read_any_from(File::open(src_path)?, &ReadOptions::default()).unwrap_or_else(|e| {
+            if e.is_io() {
+                terminate_with_error(format!("Failed to read rec file: {}", e))
+            } else {
+                terminate_with_error("Failed to load rec file")
+            }
+        });
 
         // From marbleblast.exe we need to inject the rec verifier script
         let mut installed_mod = false;
@@ -237,24 +471,9 @@ fn main() -> Result<(), Error> {
             println!("BONUS TIME: N/A");
             println!("GEM COUNT: N/A");
         }
-        println!("FRAMES: {}", recording.frames.len());
-
-        // Attempt to approximate FPS by ignoring the first long frames
-        let mut total_frames = 0;
-        let mut total_frame_time = 0f32;
-        let mut is_loading = true;
-        for frame in &recording.frames[10..] {
-            if is_loading && frame.delta < 50 {
-                is_loading = false;
-            }
-
-            if !is_loading {
-                total_frames += 1;
-                total_frame_time += (frame.delta as f32) / 1000f32;
-            }
-        }
-
-        println!("APPROXIMATE FPS: {}", total_frames as f32 / total_frame_time);
+        let stats = recording.stats();
+        println!("FRAMES: {}", stats.frames);
+        println!("APPROXIMATE FPS: {}", format_fps(&stats));
         println!("-----------------------");
     }
 